@@ -0,0 +1,497 @@
+//! Command line arguments and their mapping to the library
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+use jd_discovery::diagnostics::Diagnostics;
+use jd_discovery::error::Result;
+use jd_discovery::filter::{PathFilter, PathPattern};
+use jd_discovery::flatten::{self, ExpansionLimit, OnExpansion};
+use jd_discovery::input::{
+    self, CsvOptions, Header, InputFormat, OnError, Origin, ReadOptions, Reader,
+};
+use jd_discovery::output::{OutputFormat, Printer, Sink};
+use jd_discovery::path::{ArrayMode, Path, PathStyle};
+use jd_discovery::profile::{ProfileAction, ProfileOptions};
+use jd_discovery::scope::Scope;
+use jd_discovery::values::{Normalization, Normalize, NullSemantics, ValueOptions};
+use jd_discovery::{FdDiscovery, IndDiscovery, KeyDiscovery};
+
+#[derive(Args, Debug)]
+pub struct InputArgs {
+    #[clap(value_name = "INPUT", default_value = "-")]
+    /// Input files, directories, or glob patterns (- for standard input)
+    inputs: Vec<String>,
+
+    #[clap(long, value_enum, default_value_t = InputFormat::Jsonl)]
+    /// Format of the input (JSON lines, a single JSON array, concatenated JSON, CSV, or TSV)
+    input_format: InputFormat,
+
+    #[clap(long, value_enum, default_value_t = OnError::Fail)]
+    /// How to handle input which cannot be parsed
+    on_error: OnError,
+
+    #[clap(long, value_enum, default_value_t = Header::Auto)]
+    /// Whether the first row of CSV input contains column names
+    header: Header,
+
+    #[clap(long, value_parser = parse_byte)]
+    /// Field delimiter for CSV input [default: , for CSV and tab for TSV]
+    delimiter: Option<u8>,
+
+    #[clap(long, value_parser = parse_byte, default_value = "\"")]
+    /// Quote character for CSV input
+    quote: u8,
+
+    #[clap(long, action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Convert CSV values to numbers, booleans, and nulls
+    typed: bool,
+}
+
+impl InputArgs {
+    fn options(&self) -> ReadOptions {
+        ReadOptions {
+            format: self.input_format,
+            on_error: self.on_error,
+            csv: CsvOptions {
+                header: self.header,
+                delimiter: self.delimiter,
+                quote: self.quote,
+                typed: self.typed,
+            },
+        }
+    }
+}
+
+/// Parse a single ASCII character used in CSV syntax
+fn parse_byte(value: &str) -> std::result::Result<u8, String> {
+    match value {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if value.len() == 1 && value.is_ascii() => Ok(value.as_bytes()[0]),
+        _ => Err("must be a single ASCII character".to_string()),
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ArrayArgs {
+    #[clap(long, value_enum, default_value_t = ArrayMode::Wildcard)]
+    /// How array elements appear in paths (auto indexes short arrays with a fixed length)
+    array_mode: ArrayMode,
+}
+
+#[derive(Args, Debug)]
+pub struct ValueArgs {
+    #[command(flatten)]
+    arrays: ArrayArgs,
+
+    #[clap(long, value_enum, default_value_t = NullSemantics::Ignore)]
+    /// How null values are compared
+    null_semantics: NullSemantics,
+
+    #[clap(long, action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Treat empty strings, arrays, and objects as values instead of ignoring them
+    keep_empty: bool,
+
+    #[clap(long, value_enum, value_delimiter = ',')]
+    /// Normalization steps applied to all values before they are compared
+    normalize: Vec<Normalize>,
+
+    #[clap(long, value_name = "PATH=STEPS", value_parser = parse_path_normalize)]
+    /// Normalization steps applied to values at a path instead of --normalize
+    normalize_path: Vec<(Path, Normalization)>,
+
+    #[clap(long, value_name = "PATTERN")]
+    /// Only consider paths matching a glob pattern
+    include: Vec<String>,
+
+    #[clap(long, value_name = "PATTERN")]
+    /// Ignore paths matching a glob pattern
+    exclude: Vec<String>,
+
+    #[clap(long, value_name = "RATIO")]
+    /// Skip paths where the ratio of distinct values to occurrences is above RATIO
+    max_distinct_ratio: Option<f64>,
+
+    #[clap(long, value_name = "LENGTH")]
+    /// Skip paths where the average length of strings is above LENGTH
+    max_avg_length: Option<f64>,
+
+    #[clap(long, value_name = "N")]
+    /// Skip paths with fewer than N distinct values
+    min_distinct: Option<u64>,
+
+    #[clap(long, value_name = "N")]
+    /// Skip paths with more than N distinct values
+    max_distinct: Option<u64>,
+
+    #[clap(long, value_enum, default_value_t = ProfileAction::Exclude)]
+    /// Whether paths which fail the thresholds above are excluded or only flagged
+    profile_action: ProfileAction,
+
+    #[command(flatten)]
+    expansion: ExpansionArgs,
+}
+
+impl ValueArgs {
+    fn options(&self) -> ValueOptions {
+        ValueOptions {
+            nulls: self.null_semantics,
+            keep_empty: self.keep_empty,
+            normalize: Normalization::from_steps(&self.normalize),
+            path_normalize: self.normalize_path.iter().cloned().collect(),
+            paths: PathFilter {
+                include: self.include.iter().map(|p| PathPattern::new(p)).collect(),
+                exclude: self.exclude.iter().map(|p| PathPattern::new(p)).collect(),
+            },
+            profile: ProfileOptions {
+                max_distinct_ratio: self.max_distinct_ratio,
+                max_avg_length: self.max_avg_length,
+                min_distinct: self.min_distinct,
+                max_distinct: self.max_distinct,
+                action: self.profile_action,
+            },
+            arrays: self.arrays.array_mode,
+            expansion: self.expansion.limit(),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ScopeArgs {
+    #[clap(long, value_name = "PATH")]
+    /// Use each value at PATH (e.g. items[*]) as a row instead of whole documents
+    scope: Option<String>,
+
+    #[clap(long, requires = "scope", action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Include fields outside of --scope in each row
+    inherit: bool,
+}
+
+impl ScopeArgs {
+    fn scope(&self) -> Option<Scope> {
+        self.scope
+            .as_ref()
+            .map(|path| Scope::new(path).inherit(self.inherit))
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ExpansionArgs {
+    #[clap(long, value_name = "N")]
+    /// Limit the number of rows each document is flattened into in static mode
+    max_rows: Option<u64>,
+
+    #[clap(long, value_enum, default_value_t = OnExpansion::Fail)]
+    /// How to handle documents which flatten into more than --max-rows rows
+    on_expansion: OnExpansion,
+}
+
+impl ExpansionArgs {
+    fn limit(&self) -> Option<ExpansionLimit> {
+        self.max_rows.map(|rows| ExpansionLimit {
+            rows,
+            action: self.on_expansion,
+        })
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct FlattenArgs {
+    #[command(flatten)]
+    arrays: ArrayArgs,
+
+    #[command(flatten)]
+    expansion: ExpansionArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+/// Parse a path and a comma-separated list of normalization steps
+fn parse_path_normalize(value: &str) -> std::result::Result<(Path, Normalization), String> {
+    let (path, steps) = value
+        .rsplit_once('=')
+        .ok_or_else(|| "must be of the form PATH=STEPS".to_string())?;
+    let steps = steps
+        .split(',')
+        .filter(|step| !step.is_empty())
+        .map(|step| Normalize::from_str(step, false))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    Ok((Path::parse(path), Normalization::from_steps(&steps)))
+}
+
+/// Report the number of documents skipped due to errors
+fn print_summary(skipped: &Cell<usize>) {
+    if skipped.get() > 0 {
+        eprintln!("Invalid documents skipped: {}", skipped.get());
+    }
+}
+
+/// Limit the number of threads used for discovery
+fn configure_threads(threads: Option<usize>) {
+    if let Some(threads) = threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("Thread pool should only be configured once");
+    }
+}
+
+#[derive(Args, Debug)]
+/// Functional dependency discovery
+pub struct FDArgs {
+    #[clap(short, long, default_value_t = 0.9)]
+    /// Threshold for approximate discovery
+    threshold: f64,
+
+    #[clap(short, long, action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Enable approximate discovery
+    approximate: bool,
+
+    #[clap(short='s', long="static", action=clap::ArgAction::SetFalse, default_value_t = true)]
+    /// Use static discovery
+    dynamic: bool,
+
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    /// Output format for discovered dependencies
+    output_format: OutputFormat,
+
+    #[clap(long, value_enum, default_value_t = PathStyle::Dotted)]
+    /// Syntax used to write paths in the output
+    path_style: PathStyle,
+
+    #[clap(long)]
+    /// Number of threads to use [default: number of CPUs]
+    threads: Option<usize>,
+
+    #[clap(long, value_name = "N")]
+    /// Only find dependencies with at most N paths on the left-hand side
+    max_lhs: Option<usize>,
+
+    #[clap(long, value_name = "SECONDS")]
+    /// Stop discovery after this many seconds and output partial results
+    timeout: Option<u64>,
+
+    #[clap(long, value_name = "N")]
+    /// Stop discovery after checking N candidates and output partial results
+    max_candidates: Option<usize>,
+
+    #[clap(long, action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Output constant paths as dependencies with an empty left-hand side
+    constants: bool,
+
+    #[command(flatten)]
+    scope: ScopeArgs,
+
+    #[command(flatten)]
+    values: ValueArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args, Debug)]
+/// Inclusion dependency discovery
+pub struct INDArgs {
+    #[clap(short, long, default_value_t = 0.9)]
+    /// Threshold for approximate discovery
+    threshold: f64,
+
+    #[clap(short, long, action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Enable approximate discovery
+    approximate: bool,
+
+    #[clap(short='s', long="static", action=clap::ArgAction::SetFalse, default_value_t = true)]
+    /// Use static discovery
+    dynamic: bool,
+
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    /// Output format for discovered dependencies
+    output_format: OutputFormat,
+
+    #[clap(long, value_enum, default_value_t = PathStyle::Dotted)]
+    /// Syntax used to write paths in the output
+    path_style: PathStyle,
+
+    #[clap(short, long, action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Treat each input file as a separate collection
+    collections: bool,
+
+    #[clap(short='x', long, action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Only find dependencies between different collections (implies --collections)
+    cross_collection: bool,
+
+    #[clap(long)]
+    /// Number of threads to use [default: number of CPUs]
+    threads: Option<usize>,
+
+    #[command(flatten)]
+    scope: ScopeArgs,
+
+    #[command(flatten)]
+    values: ValueArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args, Debug)]
+/// Minimal key discovery
+pub struct KeysArgs {
+    #[clap(short, long, default_value_t = 0.9)]
+    /// Threshold for approximate discovery
+    threshold: f64,
+
+    #[clap(short, long, action=clap::ArgAction::SetTrue, default_value_t = false)]
+    /// Enable approximate discovery
+    approximate: bool,
+
+    #[clap(short='s', long="static", action=clap::ArgAction::SetFalse, default_value_t = true)]
+    /// Use static discovery
+    dynamic: bool,
+
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    /// Output format for discovered keys
+    output_format: OutputFormat,
+
+    #[clap(long, value_enum, default_value_t = PathStyle::Dotted)]
+    /// Syntax used to write paths in the output
+    path_style: PathStyle,
+
+    #[clap(long)]
+    /// Number of threads to use [default: number of CPUs]
+    threads: Option<usize>,
+
+    #[clap(long, value_name = "N")]
+    /// Only find keys with at most N paths
+    max_size: Option<usize>,
+
+    #[command(flatten)]
+    scope: ScopeArgs,
+
+    #[command(flatten)]
+    values: ValueArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+pub fn fd(args: FDArgs) -> Result<()> {
+    configure_threads(args.threads);
+    let skipped = Cell::new(0);
+    let sources = input::expand_inputs(&args.input.inputs)?;
+    let reader =
+        Reader::new(sources, args.input.options(), &skipped).diagnostics(Diagnostics::stderr());
+
+    let mut printer = Printer::new(args.output_format).path_style(args.path_style);
+    let completion = FdDiscovery::new()
+        .threshold(args.threshold)
+        .approximate(args.approximate)
+        .dynamic(args.dynamic)
+        .progress(true)
+        .diagnostics(Diagnostics::stderr())
+        .max_lhs(args.max_lhs)
+        .timeout(args.timeout.map(Duration::from_secs))
+        .max_candidates(args.max_candidates)
+        .constants(args.constants)
+        .values(args.values.options())
+        .scope(args.scope.scope())
+        .try_discover_into(reader, &mut printer)?;
+    if completion.is_partial() {
+        printer.mark_partial(&completion.to_string());
+    }
+    printer.finish();
+    print_summary(&skipped);
+
+    if completion.is_partial() {
+        eprintln!("Note: results are partial since {}", completion);
+    }
+
+    Ok(())
+}
+
+pub fn ind(args: INDArgs) -> Result<()> {
+    configure_threads(args.threads);
+    let discovery = IndDiscovery::new()
+        .threshold(args.threshold)
+        .approximate(args.approximate)
+        .dynamic(args.dynamic)
+        .cross_collection(args.cross_collection)
+        .values(args.values.options())
+        .scope(args.scope.scope())
+        .progress(true)
+        .diagnostics(Diagnostics::stderr());
+    let skipped = Cell::new(0);
+    let collections = if args.collections || args.cross_collection {
+        input::expand_collections(&args.input.inputs)?
+    } else {
+        vec![(String::new(), input::expand_inputs(&args.input.inputs)?)]
+    };
+    let inds =
+        discovery.try_discover_collections(collections.into_iter().map(|(name, sources)| {
+            (
+                name,
+                Reader::new(sources, args.input.options(), &skipped)
+                    .diagnostics(Diagnostics::stderr()),
+            )
+        }))?;
+
+    let mut printer = Printer::new(args.output_format).path_style(args.path_style);
+    for ind in inds {
+        printer.emit(ind);
+    }
+    printer.finish();
+    print_summary(&skipped);
+
+    Ok(())
+}
+
+pub fn keys(args: KeysArgs) -> Result<()> {
+    configure_threads(args.threads);
+    let skipped = Cell::new(0);
+    let sources = input::expand_inputs(&args.input.inputs)?;
+    let reader =
+        Reader::new(sources, args.input.options(), &skipped).diagnostics(Diagnostics::stderr());
+
+    let mut printer = Printer::new(args.output_format).path_style(args.path_style);
+    KeyDiscovery::new()
+        .threshold(args.threshold)
+        .approximate(args.approximate)
+        .dynamic(args.dynamic)
+        .progress(true)
+        .diagnostics(Diagnostics::stderr())
+        .max_size(args.max_size)
+        .values(args.values.options())
+        .scope(args.scope.scope())
+        .try_discover_into(reader, &mut printer)?;
+    printer.finish();
+    print_summary(&skipped);
+
+    Ok(())
+}
+
+pub fn flatten(args: FlattenArgs) -> Result<()> {
+    let skipped = Cell::new(0);
+    let sources = input::expand_inputs(&args.input.inputs)?;
+    let reader =
+        Reader::new(sources, args.input.options(), &skipped).diagnostics(Diagnostics::stderr());
+    for (i, document) in reader.enumerate() {
+        let document = document?;
+        let origin = document.origin.unwrap_or(Origin::Index(i + 1));
+        let flattened = flatten::flatten_json_limited(
+            &document.value,
+            args.arrays.array_mode,
+            args.expansion.limit(),
+            &origin,
+            &Diagnostics::stderr(),
+        )?;
+        for obj in flattened {
+            println!("{}", obj.dump());
+        }
+    }
+    print_summary(&skipped);
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! Messages reported while reading input and discovering dependencies
+
+use std::fmt;
+use std::sync::Arc;
+
+/// A message about the progress of discovery or the input
+/// which does not stop discovery
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// Discovery started a new stage such as a level of the lattice
+    Progress(String),
+    /// Input was skipped or changed, or may give meaningless results
+    Warning(String),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::Progress(message) | Diagnostic::Warning(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+/// A function which reports diagnostics
+type Report = dyn Fn(&Diagnostic) + Send + Sync;
+
+/// A caller-supplied destination for diagnostics, which
+/// are dropped unless a function to report them is given
+#[derive(Clone, Default)]
+pub struct Diagnostics(Option<Arc<Report>>);
+
+impl Diagnostics {
+    /// Report diagnostics by calling a function
+    pub fn new<F>(report: F) -> Self
+    where
+        F: Fn(&Diagnostic) + Send + Sync + 'static,
+    {
+        Diagnostics(Some(Arc::new(report)))
+    }
+
+    /// Report each diagnostic as a line on standard error
+    pub fn stderr() -> Self {
+        Diagnostics::new(|diagnostic| eprintln!("{}", diagnostic))
+    }
+
+    pub fn report(&self, diagnostic: Diagnostic) {
+        if let Some(report) = &self.0 {
+            report(&diagnostic);
+        }
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = if self.0.is_some() {
+            "reported"
+        } else {
+            "dropped"
+        };
+        f.debug_tuple("Diagnostics").field(&state).finish()
+    }
+}
@@ -0,0 +1,123 @@
+//! Errors which can occur while reading and processing input
+
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+use crate::input::Origin;
+
+#[derive(Debug)]
+pub enum Error {
+    /// An input source could not be read
+    Input { source: String, error: io::Error },
+    /// A line of input was not valid JSON
+    InvalidJson {
+        source: String,
+        line: usize,
+        error: json::Error,
+    },
+    /// A line of input was not valid UTF-8
+    InvalidUtf8 {
+        source: String,
+        line: usize,
+        error: FromUtf8Error,
+    },
+    /// A row of CSV input could not be parsed
+    InvalidCsv {
+        source: String,
+        line: usize,
+        error: csv::Error,
+    },
+    /// The input has more documents (or rows in a scope) than can be identified
+    TooManyDocuments { limit: u64 },
+    /// The input has more distinct values than can be identified
+    TooManyValues { limit: u64 },
+    /// A document flattens into more values than allowed
+    TooManyRows {
+        document: Origin,
+        rows: u64,
+        limit: u64,
+    },
+}
+
+impl Error {
+    /// The process exit code for this error following sysexits.h
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Input { .. } => 74,
+            Error::InvalidJson { .. } | Error::InvalidCsv { .. } => 65,
+            Error::InvalidUtf8 { .. } => 65,
+            Error::TooManyDocuments { .. } | Error::TooManyValues { .. } => 65,
+            Error::TooManyRows { .. } => 65,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Input { source, error } => write!(f, "Error reading {}: {}", source, error),
+            Error::InvalidJson {
+                source,
+                line,
+                error,
+            } => write!(
+                f,
+                "Found invalid JSON on line {} of {}: {}",
+                line, source, error
+            ),
+            Error::InvalidCsv {
+                source,
+                line,
+                error,
+            } => write!(
+                f,
+                "Found invalid CSV on line {} of {}: {}",
+                line, source, error
+            ),
+            Error::InvalidUtf8 {
+                source,
+                line,
+                error,
+            } => write!(
+                f,
+                "Found invalid UTF-8 on line {} of {}: {}",
+                line, source, error
+            ),
+            Error::TooManyDocuments { limit } => {
+                write!(
+                    f,
+                    "Input contains more than {} documents or scoped rows",
+                    limit
+                )
+            }
+            Error::TooManyValues { limit } => {
+                write!(f, "Input contains more than {} distinct values", limit)
+            }
+            Error::TooManyRows {
+                document,
+                rows,
+                limit,
+            } => write!(
+                f,
+                "Found {} rows when expanding {}, more than the limit of {}",
+                rows, document, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Input { error, .. } => Some(error),
+            Error::InvalidJson { error, .. } => Some(error),
+            Error::InvalidCsv { error, .. } => Some(error),
+            Error::InvalidUtf8 { error, .. } => Some(error),
+            Error::TooManyDocuments { .. } | Error::TooManyValues { .. } => None,
+            Error::TooManyRows { .. } => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
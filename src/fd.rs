@@ -1,93 +1,171 @@
-use crate::flatten::flatten_json;
-
-use std::collections::HashMap;
-use std::io::{self, BufRead};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::error::Error;
+use crate::flatten::flatten_rows_limited;
+use crate::input::{locate, Document, Origin};
+use crate::output::{Dependency, Sink};
+use crate::partition::Partition;
+use crate::path::{self, Path, PathStyle};
+use crate::profile::{flagged_paths, screen_paths};
+use crate::scope::{scoped_documents, Row, Scope};
+use crate::values::{ValueOptions, Values};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::iter::FromIterator;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
-use clap::Args;
-use indicatif::ProgressBar;
 use itertools::Itertools;
+use json::object;
+use rayon::prelude::*;
 use roaring::bitmap::RoaringBitmap;
 
+/// A functional dependency between paths
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionalDependency {
+    pub lhs: Vec<String>,
+    pub rhs: String,
+    /// Minimum fraction of documents which must be removed
+    /// for the dependency to hold exactly (approximate only)
+    pub error: Option<f64>,
+    /// Number of documents satisfying the dependency (approximate only)
+    pub support: Option<u64>,
+    /// The value of the RHS for constant paths with an empty LHS
+    pub value: Option<json::JsonValue>,
+    /// Paths in the dependency which failed the profiling thresholds
+    pub flagged: Vec<String>,
+}
+
+impl Dependency for FunctionalDependency {
+    fn to_json(&self) -> json::JsonValue {
+        let mut obj = object! {
+            lhs: self.lhs.clone(),
+            rhs: self.rhs.clone(),
+        };
+        if let Some(error) = self.error {
+            obj["error"] = error.into();
+        }
+        if let Some(support) = self.support {
+            obj["support"] = support.into();
+        }
+        if let Some(value) = &self.value {
+            obj["value"] = value.clone();
+        }
+        if !self.flagged.is_empty() {
+            obj["flagged"] = self.flagged.clone().into();
+        }
+
+        obj
+    }
+
+    fn to_text(&self) -> String {
+        let rhs = match &self.value {
+            Some(value) => format!("{} = {}", self.rhs, value.dump()),
+            None => self.rhs.clone(),
+        };
+        match self.error {
+            Some(error) => format!("{:?} -> {} (error {:.3})", self.lhs, rhs, error),
+            None => format!("{:?} -> {}", self.lhs, rhs),
+        }
+    }
+
+    fn with_path_style(self, style: PathStyle) -> Self {
+        FunctionalDependency {
+            lhs: self.lhs.iter().map(|p| path::format(p, style)).collect(),
+            rhs: path::format(&self.rhs, style),
+            flagged: self
+                .flagged
+                .iter()
+                .map(|p| path::format(p, style))
+                .collect(),
+            ..self
+        }
+    }
+}
+
 #[derive(Debug)]
-struct Element {
-    bitmap: RoaringBitmap,
-    valid: bool,
+pub(crate) struct Element {
+    pub bitmap: RoaringBitmap,
+    pub valid: bool,
 }
 
-type Level = HashMap<RoaringBitmap, Element>;
-type Bitmaps = HashMap<RoaringBitmap, RoaringBitmap>;
+pub(crate) type Level = HashMap<RoaringBitmap, Element>;
+pub(crate) type Partitions = HashMap<RoaringBitmap, Partition>;
+
+/// The maximum number of documents which can be processed
+///
+/// Documents are identified by a `u32` and the number
+/// of documents must also fit in a `u32`.
+pub const MAX_DOCUMENTS: usize = u32::MAX as usize;
+
+/// Convert the position of a row to its identifier
+///
+/// Rows are whole documents unless a scope splits them.
+fn row_id(index: usize) -> Result<u32, Error> {
+    if index < MAX_DOCUMENTS {
+        Ok(index as u32)
+    } else {
+        Err(Error::TooManyDocuments {
+            limit: MAX_DOCUMENTS as u64,
+        })
+    }
+}
 
 fn collect_values(
-    lineno: usize,
-    all_values: &mut HashMap<String, usize>,
-    constants: &mut HashMap<String, bool>,
-    first_values: &mut HashMap<String, usize>,
-    load_partitions: &mut HashMap<String, HashMap<usize, RoaringBitmap>>,
-    path: &str,
+    row: u32,
+    all_values: &mut Values,
+    constants: &mut HashMap<Path, bool>,
+    first_values: &mut HashMap<Path, usize>,
+    load_partitions: &mut HashMap<Path, HashMap<usize, RoaringBitmap>>,
+    path: &Path,
     value: &json::JsonValue,
 ) {
-    if value.is_object() {
-        for (dict_key, dict_value) in value.entries() {
-            let mut new_path: String = path.to_owned();
-
-            if !new_path.is_empty() {
-                new_path.push('.');
-            }
-            new_path.push_str(dict_key);
+    if all_values.is_excluded(path) {
+        return;
+    }
 
+    if value.is_object() && all_values.is_nested(value) {
+        for (dict_key, dict_value) in value.entries() {
             collect_values(
-                lineno,
+                row,
                 all_values,
                 constants,
                 first_values,
                 load_partitions,
-                &new_path,
+                &path.key(dict_key),
                 dict_value,
             );
         }
-    } else if value.is_array() {
+    } else if value.is_array() && all_values.is_nested(value) {
         // Loop through all array elements and add [] to the path
-        for list_value in value.members() {
-            let mut new_path: String = path.to_owned();
-            new_path.push_str("[*]");
-
-            collect_values(
-                lineno,
-                all_values,
-                constants,
-                first_values,
-                load_partitions,
-                &new_path,
-                list_value,
-            );
+        for (i, list_value) in value.members().enumerate() {
+            for new_path in all_values.element_paths(path, i, value.len()) {
+                collect_values(
+                    row,
+                    all_values,
+                    constants,
+                    first_values,
+                    load_partitions,
+                    &new_path,
+                    list_value,
+                );
+            }
         }
-    } else if !value.is_null() && (!value.is_string() || !value.is_empty()) {
+    } else if let Some(str_index) = all_values.intern(path, value) {
         // Find or add the new value
-        let str_value = value.dump();
-        let str_index: usize = match all_values.get(&str_value) {
-            Some(index) => *index,
-            None => {
-                let new_index = all_values.len();
-                all_values.insert(str_value.clone(), new_index);
-
-                new_index
-            }
-        };
 
         if !first_values.contains_key(path) {
             // Track the first value observed for a path
-            first_values.insert(path.to_owned(), str_index);
-            constants.insert(path.to_owned(), true);
+            first_values.insert(path.clone(), str_index);
+            constants.insert(path.clone(), true);
         } else if *first_values.get(path).unwrap() != str_index {
             // If we see a new value at a path, we no longer have a constant
-            constants.insert(path.to_owned(), false);
+            constants.insert(path.clone(), false);
         }
 
         // Add a hash map from paths to values if not created
         if !load_partitions.contains_key(path) {
-            load_partitions.insert(path.to_owned(), HashMap::new());
+            load_partitions.insert(path.clone(), HashMap::new());
         }
 
         // Add a hash map from values to document numbers if not created
@@ -97,267 +175,576 @@ fn collect_values(
         }
 
         // Store the presence of this value at this path in this document
-        let lineno32: u32 = lineno as u32;
-        path_map.get_mut(&str_index).unwrap().insert(lineno32);
+        path_map.get_mut(&str_index).unwrap().insert(row);
     }
 }
 
-fn index(i: u32, j: u32) -> u32 {
-    // Ensure the values are ordered smallest first
-    // Since the relationship is bidirectional,
-    // we want to represent both the same
-    let (i2, j2) = if i > j { (j, i) } else { (i, j) };
-
-    // Our index is simply a linearization of a lower
-    // triangular adjacency matrix laid out as follows:
-    // j\i|0 1 2 3 4
-    // ------------
-    // 0  |
-    // 1  |0
-    // 2  |1 2
-    // 3  |3 4 5
-    // 4  |6 7 8 9
-    (j2 * (j2 - 1)) / 2 + i2
-}
-
-fn reverse_index(index: u32) -> (u32, u32) {
-    // Ignore the addition of i in the previous equation
-    // and solve: j * (j - 1) / 2 = index for j
-    // taking the floor to handle the addition of i
-    let j = ((((8 * index + 1) as f64).sqrt() + 1.0) / 2.0).floor() as u32;
-
-    // Solve for i using the equation from index()
-    let i = index - (j * (j - 1)) / 2;
-
-    (i, j)
-}
-
-fn initialize_bitmaps(
-    load_partitions: &HashMap<String, HashMap<usize, RoaringBitmap>>,
-    paths: &HashMap<u32, String>,
-    max_lineno: u32,
-) -> HashMap<RoaringBitmap, RoaringBitmap> {
-    let mut all_set = RoaringBitmap::new();
-    all_set.insert_range(0..index(max_lineno - 1, max_lineno - 2) + 1);
-
-    let mut bitmaps = HashMap::new();
-    bitmaps.insert(RoaringBitmap::new(), all_set);
+fn initialize_partitions(
+    load_partitions: &HashMap<Path, HashMap<usize, RoaringBitmap>>,
+    paths: &HashMap<u32, Path>,
+    row_count: u32,
+) -> Partitions {
+    // The empty set of paths places all documents in the same class
+    let mut partitions = HashMap::new();
+    partitions.insert(RoaringBitmap::new(), Partition::all(row_count));
 
     for (path_index, path) in paths {
-        let mut bitmap = RoaringBitmap::new();
-        for values in load_partitions.get(path).unwrap().values() {
-            for (i, j) in values
-                .iter()
-                .collect::<Vec<u32>>()
-                .iter()
-                .tuple_combinations::<(_, _)>()
-            {
-                bitmap.insert(index(*i, *j));
-            }
-        }
-
-        bitmaps.insert(RoaringBitmap::from(*path_index), bitmap);
+        let partition = Partition::from_values(load_partitions.get(path).unwrap());
+        partitions.insert(RoaringBitmap::from(*path_index), partition);
     }
 
-    bitmaps
+    partitions
 }
 
-macro_rules! hashcomp {
-    ($name:ident = $k:expr => $v:expr; for $i:ident in $itr:expr) => {
-        let mut $name: Level = HashMap::new();
-        for $i in $itr {
-            $name.insert($k, $v);
-        }
-    };
+/// Paths observed in the input along with their partitions
+pub(crate) struct Collected {
+    pub paths: HashMap<u32, Path>,
+    pub partitions: Partitions,
+    /// The documents which have a value for each path
+    pub presence: HashMap<u32, RoaringBitmap>,
+    /// Paths with a single value along with that value
+    /// and the number of documents containing it
+    pub constants: Vec<(Path, json::JsonValue, u64)>,
+    /// Paths which were kept despite failing the profiling thresholds
+    pub flagged: HashSet<String>,
+    /// The number of documents, or of rows in a scope
+    pub row_count: usize,
 }
 
-#[derive(Args, Debug)]
-/// Functional dependency discovery
-pub struct FDArgs {
-    #[clap(short, long, default_value_t = 0.9)]
-    /// Threshold for approximate discovery
-    threshold: f64,
-
-    #[clap(short, long, action=clap::ArgAction::SetTrue, default_value_t = false)]
-    /// Enable approximate discovery
-    approximate: bool,
-
-    #[clap(short='s', long="static", action=clap::ArgAction::SetFalse, default_value_t = true)]
-    /// Use static discovery
+/// Collect the values of all non-constant paths in a set of
+/// documents and build their partitions, if there are any documents
+pub(crate) fn collect_partitions<I, E>(
+    documents: I,
     dynamic: bool,
-}
-
-pub fn discover(args: FDArgs) {
-    let mut all_values: HashMap<String, usize> = HashMap::new();
-    let mut constants: HashMap<String, bool> = HashMap::new();
-    let mut first_values: HashMap<String, usize> = HashMap::new();
-    let mut load_partitions: HashMap<String, HashMap<usize, RoaringBitmap>> = HashMap::new();
+    options: ValueOptions,
+    progress: bool,
+    diagnostics: &Diagnostics,
+) -> Result<Option<Collected>, E>
+where
+    I: IntoIterator<Item = Result<(Origin, Row), E>>,
+    E: From<Error>,
+{
+    let mut all_values = Values::new(options);
+    let mut constants: HashMap<Path, bool> = HashMap::new();
+    let mut first_values: HashMap<Path, usize> = HashMap::new();
+    let mut load_partitions: HashMap<Path, HashMap<usize, RoaringBitmap>> = HashMap::new();
 
     // Initialize spinner
-    let mut spinner = ProgressBar::new_spinner().with_message("Reading input…");
-    spinner.enable_steady_tick(Duration::from_millis(100));
+    let mut spinner = crate::spinner(progress, "Reading input…");
 
     // Process input and collect values
     let start = Instant::now();
-    let stdin = io::stdin();
-    let mut max_lineno = 0;
-    let lines = stdin.lock().lines().enumerate();
-    for (lineno, line) in lines {
-        let parsed =
-            json::parse(&line.expect("Error reading input")).expect("Found invalid JSON line");
-        if args.dynamic {
-            collect_values(
-                lineno,
-                &mut all_values,
-                &mut constants,
-                &mut first_values,
-                &mut load_partitions,
-                "",
-                &parsed,
-            );
-        } else {
-            for obj in flatten_json(&parsed) {
+    let mut row_count = 0;
+    for (index, parsed) in documents.into_iter().enumerate() {
+        let (origin, parts) = parsed?;
+        let row = row_id(index)?;
+        for (prefix, value) in &parts {
+            all_values.record_arrays(prefix, value);
+        }
+        if dynamic {
+            for (prefix, value) in &parts {
                 collect_values(
-                    lineno,
+                    row,
                     &mut all_values,
                     &mut constants,
                     &mut first_values,
                     &mut load_partitions,
-                    "",
-                    &obj,
+                    prefix,
+                    value,
                 );
             }
+        } else {
+            // Keys of flattened documents are already complete paths
+            let options = all_values.options();
+            let parts = parts
+                .iter()
+                .map(|(prefix, value)| (prefix.clone(), value))
+                .collect();
+            let flattened = flatten_rows_limited(
+                parts,
+                options.arrays,
+                options.expansion,
+                &origin,
+                diagnostics,
+            )?;
+            for flat_row in flattened {
+                for (path, value) in flat_row {
+                    collect_values(
+                        row,
+                        &mut all_values,
+                        &mut constants,
+                        &mut first_values,
+                        &mut load_partitions,
+                        &path,
+                        &value,
+                    );
+                }
+            }
         }
-        max_lineno = lineno;
+        row_count = index + 1;
     }
-    max_lineno += 1;
 
     // Stop if there is no input
-    if max_lineno == 1 {
-        std::process::exit(0);
+    if row_count == 0 {
+        return Ok(None);
     }
 
+    // Only keep one representation of each array
+    load_partitions.retain(|path, _| all_values.keeps_path(path));
+    constants.retain(|path, _| all_values.keeps_path(path));
+
     // Remove spinner
     let mut duration = start.elapsed();
     spinner.disable_steady_tick();
     spinner.finish_with_message(format!("Collected values in {:?}", duration));
 
     // Remove any constant values
+    let mut constant_values = Vec::new();
     for (path, is_constant) in constants {
         if is_constant {
-            load_partitions.remove(&path);
+            let values = load_partitions.remove(&path).unwrap();
+            let (index, rows) = values.iter().next().unwrap();
+            constant_values.push((path, all_values.value(*index), rows.len()));
         }
     }
 
+    // Skip paths which are unlikely to be meaningful
+    let flagged = screen_paths(
+        &all_values.options().profile,
+        &all_values.profiles,
+        &mut load_partitions,
+        |values| values.len() as u64,
+        diagnostics,
+    )
+    .into_iter()
+    .filter(|(path, _)| load_partitions.contains_key(path))
+    .map(|(path, _)| path.to_string())
+    .collect();
+
     // Reinitialize spinner
-    spinner = ProgressBar::new_spinner().with_message("Building bitmaps…");
-    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner = crate::spinner(progress, "Building partitions…");
 
     // Build a map from all paths to an integer index
     let paths = load_partitions
         .keys()
         .enumerate()
         .map(|(i, k)| (i as u32, k.clone()))
-        .collect::<HashMap<u32, String>>();
+        .collect::<HashMap<u32, Path>>();
 
-    // Construct the set of bitmaps for each path based on the observed data
-    let mut bitmaps = initialize_bitmaps(&load_partitions, &paths, max_lineno as u32);
+    // Construct the partition for each path based on the observed data
+    let partitions = initialize_partitions(&load_partitions, &paths, row_count as u32);
+
+    // Track which documents have any value for each path
+    let presence = paths
+        .iter()
+        .map(|(i, path)| {
+            let rows = load_partitions.get(path).unwrap().values();
+            (*i, rows.fold(RoaringBitmap::new(), |all, rows| all | rows))
+        })
+        .collect();
 
     // Remove spinner
     duration = start.elapsed();
     spinner.disable_steady_tick();
-    spinner.finish_with_message(format!("Built bitmaps in {:?}", duration));
+    spinner.finish_with_message(format!("Built partitions in {:?}", duration));
+
+    Ok(Some(Collected {
+        paths,
+        partitions,
+        presence,
+        constants: constant_values,
+        flagged,
+        row_count,
+    }))
+}
 
-    // Construct a bitmap reprenting all paths
-    let mut all = RoaringBitmap::new();
-    all.insert_range(0..load_partitions.len() as u32);
-
-    // Initialize the first two levels
-    //
-    // Note that we represent the lattice as a hash map with the keys
-    // being a bitmap representing that lattice element and the values
-    // as C+ according to the TANE paper. The value also tracks a valid
-    // bit which we use so that we can continue storing a lattice element
-    // to track the C+ value even after that element is pruned.
-    let mut level0 = HashMap::from([(
-        RoaringBitmap::new(),
-        Element {
-            bitmap: all,
-            valid: true,
-        },
-    )]);
-    hashcomp!(level1 = RoaringBitmap::from(*a) => Element {bitmap: RoaringBitmap::new(), valid: true}; for a in paths.keys());
-
-    for i in 0..load_partitions.len() {
-        eprintln!("Starting level {}...", i + 1);
-
-        // Calculate dependencies at this level of the lattice
-        compute_dependencies(
-            &level0,
-            &mut level1,
-            &bitmaps,
-            &paths,
-            max_lineno as u32,
-            args.approximate,
-            args.threshold,
-        );
-        prune(
-            &mut level1,
-            &bitmaps,
-            &paths,
-            max_lineno as u32,
-            args.approximate,
-            args.threshold,
-        );
+macro_rules! hashcomp {
+    ($name:ident = $k:expr => $v:expr; for $i:ident in $itr:expr) => {
+        let mut $name: Level = HashMap::new();
+        for $i in $itr {
+            $name.insert($k, $v);
+        }
+    };
+}
 
-        // Pruning may have left a level empty, so we can't continue
-        if level1.is_empty() {
-            break;
+/// Builder for functional dependency discovery
+#[derive(Clone, Debug)]
+pub struct FdDiscovery {
+    threshold: f64,
+    approximate: bool,
+    dynamic: bool,
+    progress: bool,
+    diagnostics: Diagnostics,
+    max_lhs: Option<usize>,
+    timeout: Option<Duration>,
+    max_candidates: Option<usize>,
+    constants: bool,
+    values: ValueOptions,
+    scope: Option<Scope>,
+}
+
+impl Default for FdDiscovery {
+    fn default() -> Self {
+        FdDiscovery {
+            threshold: 0.9,
+            approximate: false,
+            dynamic: true,
+            progress: false,
+            diagnostics: Diagnostics::default(),
+            max_lhs: None,
+            timeout: None,
+            max_candidates: None,
+            constants: false,
+            values: ValueOptions::default(),
+            scope: None,
         }
+    }
+}
+
+/// Whether discovery searched the whole lattice or stopped early
+/// because a budget was exhausted, leaving the results partial
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Completion {
+    Complete,
+    TimedOut,
+    CandidateLimit,
+}
 
-        // Generate the next lattice level
-        level0 = level1;
-        level1 = generate_next_level(&level0, &mut bitmaps);
+impl Completion {
+    pub fn is_partial(&self) -> bool {
+        *self != Completion::Complete
+    }
+}
 
-        // We may still not have valid levels to continue
-        if level1.is_empty() {
-            break;
+impl fmt::Display for Completion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Completion::Complete => write!(f, "discovery completed"),
+            Completion::TimedOut => write!(f, "discovery reached the time limit"),
+            Completion::CandidateLimit => write!(f, "discovery reached the candidate limit"),
+        }
+    }
+}
+
+impl FdDiscovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the threshold for approximate discovery
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Enable approximate discovery
+    pub fn approximate(mut self, approximate: bool) -> Self {
+        self.approximate = approximate;
+        self
+    }
+
+    /// Use dynamic discovery instead of flattening documents
+    pub fn dynamic(mut self, dynamic: bool) -> Self {
+        self.dynamic = dynamic;
+        self
+    }
+
+    /// Show progress on standard error
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Report progress and problems with the input as diagnostics
+    pub fn diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Only find dependencies with at most this many paths on the LHS
+    pub fn max_lhs(mut self, max_lhs: Option<usize>) -> Self {
+        self.max_lhs = max_lhs;
+        self
+    }
+
+    /// Stop discovery once this much time has passed
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Stop discovery before checking more than this many lattice elements
+    pub fn max_candidates(mut self, max_candidates: Option<usize>) -> Self {
+        self.max_candidates = max_candidates;
+        self
+    }
+
+    /// Set which values are considered and how nulls are compared
+    pub fn values(mut self, values: ValueOptions) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// Use the values at a path as rows instead of whole documents
+    pub fn scope(mut self, scope: Option<Scope>) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Output paths with a single value as dependencies with an empty LHS
+    pub fn constants(mut self, constants: bool) -> Self {
+        self.constants = constants;
+        self
+    }
+
+    /// Check if discovery should stop before the next lattice level
+    fn check_budget(&self, deadline: Option<Instant>, candidates: usize) -> Completion {
+        if expired(deadline) {
+            Completion::TimedOut
+        } else if self.max_candidates.is_some_and(|max| candidates > max) {
+            Completion::CandidateLimit
+        } else {
+            Completion::Complete
+        }
+    }
+
+    /// Discover all functional dependencies in a collection of documents
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than [`MAX_DOCUMENTS`] documents or rows in
+    /// a scope, or if static discovery flattens a document into more rows
+    /// than [`ValueOptions::expansion`] allows with
+    /// [`OnExpansion::Fail`](crate::flatten::OnExpansion::Fail).
+    /// Use [`try_discover_into`](Self::try_discover_into) to handle these as
+    /// errors instead.
+    pub fn discover<I>(&self, documents: I) -> Vec<FunctionalDependency>
+    where
+        I: IntoIterator<Item = json::JsonValue>,
+    {
+        let mut fds = Vec::new();
+        self.discover_into(documents, &mut fds);
+
+        fds
+    }
+
+    /// Discover functional dependencies and emit them to a sink
+    /// as each level of the lattice is completed
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than [`MAX_DOCUMENTS`] documents or rows in
+    /// a scope, or if static discovery flattens a document into more rows
+    /// than [`ValueOptions::expansion`] allows with
+    /// [`OnExpansion::Fail`](crate::flatten::OnExpansion::Fail).
+    /// Use [`try_discover_into`](Self::try_discover_into) to handle these as
+    /// errors instead.
+    pub fn discover_into<I, S>(&self, documents: I, sink: &mut S) -> Completion
+    where
+        I: IntoIterator<Item = json::JsonValue>,
+        S: Sink<FunctionalDependency>,
+    {
+        self.try_discover_into(documents.into_iter().map(Ok::<_, Error>), sink)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Discover functional dependencies from documents which may fail to
+    /// load, stopping at the first error before any dependency is emitted
+    pub fn try_discover_into<I, D, S, E>(&self, documents: I, sink: &mut S) -> Result<Completion, E>
+    where
+        I: IntoIterator<Item = Result<D, E>>,
+        D: Into<Document>,
+        S: Sink<FunctionalDependency>,
+        E: From<Error>,
+    {
+        let start = Instant::now();
+        let Collected {
+            paths,
+            mut partitions,
+            mut constants,
+            flagged,
+            row_count,
+            ..
+        } = match collect_partitions(
+            scoped_documents(&self.scope, locate(documents)),
+            self.dynamic,
+            self.values.clone(),
+            self.progress,
+            &self.diagnostics,
+        )? {
+            Some(collected) => collected,
+            None => return Ok(Completion::Complete),
+        };
+
+        // Constant paths are not part of the lattice so they are output first
+        if self.constants {
+            constants.sort_by_cached_key(|(path, _, _)| path.to_string());
+            for (path, value, present) in constants {
+                // Documents without the path violate the dependency
+                let violations = row_count as u64 - present;
+                if check_violations(
+                    violations,
+                    row_count as u32,
+                    self.approximate,
+                    self.threshold,
+                ) {
+                    sink.emit(FunctionalDependency {
+                        lhs: Vec::new(),
+                        rhs: path.to_string(),
+                        error: self
+                            .approximate
+                            .then(|| violations as f64 / row_count as f64),
+                        support: self.approximate.then_some(present),
+                        value: Some(value),
+                        flagged: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        // Construct a bitmap reprenting all paths
+        let mut all = RoaringBitmap::new();
+        all.insert_range(0..paths.len() as u32);
+
+        // Initialize the first two levels
+        //
+        // Note that we represent the lattice as a hash map with the keys
+        // being a bitmap representing that lattice element and the values
+        // as C+ according to the TANE paper. The value also tracks a valid
+        // bit which we use so that we can continue storing a lattice element
+        // to track the C+ value even after that element is pruned.
+        let mut level0 = HashMap::from([(
+            RoaringBitmap::new(),
+            Element {
+                bitmap: all,
+                valid: true,
+            },
+        )]);
+        hashcomp!(level1 = RoaringBitmap::from(*a) => Element {bitmap: RoaringBitmap::new(), valid: true}; for a in paths.keys());
+
+        let deadline = self.timeout.map(|timeout| start + timeout);
+        let mut candidates = level1.len();
+        for i in 0..paths.len() {
+            // Stop before starting a level which exceeds the budget
+            let completion = self.check_budget(deadline, candidates);
+            if completion.is_partial() {
+                return Ok(completion);
+            }
+
+            self.diagnostics
+                .report(Diagnostic::Progress(format!("Starting level {}...", i + 1)));
+
+            // Calculate dependencies at this level of the lattice
+            let mut found = Vec::new();
+            let mut completion = compute_dependencies(
+                &level0,
+                &mut level1,
+                &partitions,
+                &paths,
+                row_count as u32,
+                self.approximate,
+                self.threshold,
+                deadline,
+                &mut found,
+            );
+
+            // Pruning relies on C+ being complete for the whole level
+            if !completion.is_partial() {
+                completion = prune(
+                    &mut level1,
+                    &partitions,
+                    &paths,
+                    row_count as u32,
+                    self.approximate,
+                    self.threshold,
+                    deadline,
+                    &mut found,
+                );
+            }
+
+            // Keys found when pruning can have one more path
+            // on the LHS than the dependencies at this level
+            if let Some(max_lhs) = self.max_lhs {
+                found.retain(|fd| fd.lhs.len() <= max_lhs);
+            }
+
+            // Output dependencies in a consistent order since
+            // lattice elements are visited in hash order
+            found.sort_by(|a, b| (a.lhs.len(), &a.lhs, &a.rhs).cmp(&(b.lhs.len(), &b.lhs, &b.rhs)));
+            for mut fd in found {
+                fd.flagged = flagged_paths(&flagged, fd.lhs.iter().chain([&fd.rhs]));
+                sink.emit(fd);
+            }
+
+            // Dependencies found before the deadline still hold
+            if completion.is_partial() {
+                return Ok(completion);
+            }
+
+            // Pruning may have left a level empty, so we can't continue
+            if level1.is_empty() {
+                break;
+            }
+
+            // The next level would only find dependencies with a larger LHS
+            if self.max_lhs.is_some_and(|max_lhs| i >= max_lhs) {
+                break;
+            }
+
+            // Only partitions for single paths and the previous
+            // level are needed to generate and check the next level
+            let size = level1.keys().next().unwrap().len();
+            partitions.retain(|x, _| x.len() <= 1 || x.len() >= size);
+
+            // Generate the next lattice level
+            level0 = level1;
+            level1 = generate_next_level(&level0, &mut partitions);
+            candidates += level1.len();
+
+            // We may still not have valid levels to continue
+            if level1.is_empty() {
+                break;
+            }
         }
+
+        Ok(Completion::Complete)
     }
 }
 
+/// Generate new lattice elements from a prefix block along
+/// with the partitions for those which are still valid
 fn process_block(
     level: &Level,
-    new_level: &mut Level,
-    bitmaps: &mut Bitmaps,
-    k: Vec<RoaringBitmap>,
-) {
+    partitions: &Partitions,
+    k: &[RoaringBitmap],
+) -> Vec<(RoaringBitmap, Element, Option<Partition>)> {
+    let mut elements = Vec::new();
+
     // Generate all combinations of elements in the prefix block
     for (y, z) in k.iter().tuple_combinations::<(_, _)>() {
         let x = y | z;
 
         // Check if all required subsets are contained in the lattice
         if check_included(&x, level) {
-            let valid = if level.contains_key(y) && level.contains_key(z) {
-                // Generate the new bitmap for this potential LHS
-                let y_bitmap = bitmaps.get(y).unwrap();
-                let z_bitmap = bitmaps.get(z).unwrap();
-                bitmaps.insert(x.clone(), y_bitmap & z_bitmap);
-                true
+            let partition = if level.contains_key(y) && level.contains_key(z) {
+                // Generate the new partition for this potential LHS
+                let y_partition = partitions.get(y).unwrap();
+                let z_partition = partitions.get(z).unwrap();
+                Some(y_partition.product(z_partition))
             } else {
-                false
+                None
             };
 
             // Add a new lattice element
-            new_level.insert(
-                x,
-                Element {
-                    bitmap: RoaringBitmap::new(),
-                    valid,
-                },
-            );
+            let element = Element {
+                bitmap: RoaringBitmap::new(),
+                valid: partition.is_some(),
+            };
+            elements.push((x, element, partition));
         }
     }
+
+    elements
 }
 
 /// Implements the test from line 5 of GENERATE_NEXT_LEVEL in TANE
@@ -408,72 +795,124 @@ fn prefix_blocks(level: &Level) -> Vec<Vec<RoaringBitmap>> {
     blocks
 }
 
-fn generate_next_level(level: &Level, bitmaps: &mut Bitmaps) -> Level {
+pub(crate) fn generate_next_level(level: &Level, partitions: &mut Partitions) -> Level {
     let mut new_level = HashMap::new();
     let blocks = prefix_blocks(level);
 
-    for k in blocks {
-        process_block(level, &mut new_level, bitmaps, k);
+    // Prefix blocks are independent so they can be processed in parallel
+    let elements: Vec<_> = blocks
+        .par_iter()
+        .flat_map_iter(|k| process_block(level, partitions, k))
+        .collect();
+
+    for (x, element, partition) in elements {
+        if let Some(partition) = partition {
+            partitions.insert(x.clone(), partition);
+        }
+        new_level.insert(x, element);
     }
 
     new_level
 }
 
-fn check_bitmap(
-    bitmap: &RoaringBitmap,
-    max_lineno: u32,
+/// Check if a dependency holds given the number of documents which
+/// must be removed for it to hold (the g3 error from TANE)
+///
+/// Exact dependencies cannot have any violations while approximate
+/// dependencies must hold in at least `threshold` of all documents.
+pub(crate) fn check_violations(
+    violations: u64,
+    row_count: u32,
     approximate: bool,
     threshold: f64,
 ) -> bool {
-    let mut violations = RoaringBitmap::new();
-    for index in bitmap {
-        // Get the index of the original paths
-        let (i, j) = reverse_index(index);
-
-        // Count violations of the dependency
-        if !violations.contains(i) && !violations.contains(j) {
-            violations.insert(i);
-            violations.insert(j);
-        }
+    if approximate {
+        let support = (row_count as u64).saturating_sub(violations);
+        support as f64 / row_count as f64 >= threshold
+    } else {
+        violations == 0
     }
-
-    // Check if the violations are below a given threshold
-    (approximate && violations.is_empty())
-        || (violations.len() as f64) / (max_lineno as f64) < (1.0 - threshold)
 }
 
-fn print_dependency(lhs: &RoaringBitmap, rhs: u32, paths: &HashMap<u32, String>) {
-    // Look up the path values by index and print the dependency
-    println!(
-        "{:?} -> {}",
-        lhs.iter()
-            .map(|b| paths.get(&b).unwrap())
+fn build_dependency(
+    lhs: &RoaringBitmap,
+    rhs: u32,
+    paths: &HashMap<u32, Path>,
+    violations: u64,
+    row_count: u32,
+    approximate: bool,
+) -> FunctionalDependency {
+    // Look up the path values by index to construct the dependency
+    FunctionalDependency {
+        lhs: lhs
+            .iter()
+            .map(|b| paths.get(&b).unwrap().to_string())
             .sorted()
-            .collect::<Vec<&String>>(),
-        paths.get(&rhs).unwrap()
-    );
+            .collect(),
+        rhs: paths.get(&rhs).unwrap().to_string(),
+        error: approximate.then(|| violations as f64 / row_count as f64),
+        support: approximate.then(|| (row_count as u64).saturating_sub(violations)),
+        value: None,
+        flagged: Vec::new(),
+    }
 }
 
-/// Implements the PRUNE procuedure from TANE
+/// A change to a lattice element made while pruning
+enum Pruned {
+    /// Update C+(X) and mark the element as pruned
+    Invalidate(RoaringBitmap),
+    /// Remove the element from the lattice
+    Remove,
+}
+
+/// Check if the deadline for discovery has passed
+fn expired(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Implements the PRUNE procuedure from TANE, stopping
+/// without checking the remaining elements at the deadline
+#[allow(clippy::too_many_arguments)]
 fn prune(
     level: &mut Level,
-    bitmaps: &Bitmaps,
-    paths: &HashMap<u32, String>,
-    max_lineno: u32,
+    partitions: &Partitions,
+    paths: &HashMap<u32, Path>,
+    row_count: u32,
     approximate: bool,
     threshold: f64,
-) {
-    let mut to_remove = Vec::new();
-    let mut invalidate = Vec::new();
-
-    for (x, l) in level.iter() {
-        // If C+(X) is empty, we can remove from the lattice
-        if l.bitmap.is_empty() {
-            to_remove.push(x.clone());
-            continue;
-        }
+    deadline: Option<Instant>,
+    sink: &mut impl Sink<FunctionalDependency>,
+) -> Completion {
+    let mut all = RoaringBitmap::new();
+    all.insert_range(0..paths.len() as u32);
+    let timed_out = AtomicBool::new(false);
+
+    // Check each lattice element in parallel and then apply the changes
+    let pruned: Vec<_> = level
+        .par_iter()
+        .filter_map(|(x, l)| {
+            if expired(deadline) {
+                timed_out.store(true, Ordering::Relaxed);
+                return None;
+            }
+
+            // If C+(X) is empty, we can remove from the lattice
+            if l.bitmap.is_empty() {
+                return Some((x.clone(), Pruned::Remove, Vec::new()));
+            }
+
+            if !l.valid {
+                return None;
+            }
 
-        if l.valid && check_bitmap(bitmaps.get(x).unwrap(), max_lineno, approximate, threshold) {
+            // Check if X is a (possibly approximate) key
+            let partition = partitions.get(x).unwrap();
+            if !check_violations(partition.key_error(), row_count, approximate, threshold) {
+                return None;
+            }
+
+            let mut fds = Vec::new();
+            let mut new_bitmap = None;
             for a in (l.bitmap.clone() - x).iter() {
                 let mut first = true;
                 let mut intersect = RoaringBitmap::new();
@@ -494,72 +933,124 @@ fn prune(
                 }
 
                 if intersect.contains(a) {
-                    print_dependency(x, a, paths);
-                    let mut all = RoaringBitmap::new();
-                    all.insert_range(0..paths.len() as u32);
-
-                    let new_bitmap = l.bitmap.clone() - (RoaringBitmap::from(a) | (all - x));
-                    invalidate.push((x.clone(), new_bitmap));
+                    // The dependency can have fewer violations than the key
+                    let rhs = partitions.get(&RoaringBitmap::from(a)).unwrap();
+                    let violations = if approximate { partition.error(rhs) } else { 0 };
+                    fds.push(build_dependency(
+                        x,
+                        a,
+                        paths,
+                        violations,
+                        row_count,
+                        approximate,
+                    ));
+
+                    new_bitmap = Some(l.bitmap.clone() - (RoaringBitmap::from(a) | (&all - x)));
                 }
             }
+
+            new_bitmap.map(|bitmap| (x.clone(), Pruned::Invalidate(bitmap), fds))
+        })
+        .collect();
+
+    for (x, change, fds) in pruned {
+        match change {
+            Pruned::Invalidate(new_bitmap) => {
+                let element = level.get_mut(&x).unwrap();
+                element.bitmap = new_bitmap;
+                element.valid = false;
+            }
+            // Remove uneeded lattice elements
+            Pruned::Remove => {
+                level.remove(&x);
+            }
         }
-    }
 
-    for (x, new_bitmap) in invalidate.iter() {
-        let mut element = level.get_mut(x).unwrap();
-        element.bitmap = new_bitmap.clone();
-        element.valid = false;
+        for fd in fds {
+            sink.emit(fd);
+        }
     }
 
-    // Remove uneeded lattice elements
-    for x in to_remove {
-        level.remove(&x);
+    if timed_out.into_inner() {
+        Completion::TimedOut
+    } else {
+        Completion::Complete
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn compute_dependencies(
     level0: &Level,
     level1: &mut Level,
-    bitmaps: &Bitmaps,
-    paths: &HashMap<u32, String>,
-    max_lineno: u32,
+    partitions: &Partitions,
+    paths: &HashMap<u32, Path>,
+    row_count: u32,
     approximate: bool,
     threshold: f64,
-) {
+    deadline: Option<Instant>,
+    sink: &mut impl Sink<FunctionalDependency>,
+) -> Completion {
     initialize_cplus_for_level(level0, level1);
+    let all = RoaringBitmap::from_iter(paths.keys().cloned());
+    let timed_out = AtomicBool::new(false);
 
-    // for each X in Ll
-    for (x, l) in level1 {
+    // for each X in Ll (in parallel since each X only updates C+(X))
+    let found: Vec<_> = level1
+        .par_iter_mut()
         // Skip elements not valid (pruned)
-        if !l.valid {
-            continue;
-        }
+        .filter(|(_, l)| l.valid)
+        .flat_map_iter(|(x, l)| {
+            let mut fds = Vec::new();
+
+            // for each A in X ^ C+(X)
+            for a in x & l.bitmap.clone() {
+                // Leave the remaining candidates unchecked at the deadline
+                if expired(deadline) {
+                    timed_out.store(true, Ordering::Relaxed);
+                    break;
+                }
 
-        // for each A in X ^ C+(X)
-        for a in x & l.bitmap.clone() {
-            let rhs = RoaringBitmap::from(a); // A
-            let lhs = x - rhs.clone(); // X \ {A}
-
-            // Check validity of X \ {A} -> A
-            if check_bitmap(
-                &(bitmaps.get(&lhs).unwrap() - bitmaps.get(&rhs).unwrap()),
-                max_lineno,
-                approximate,
-                threshold,
-            ) {
-                print_dependency(&lhs, a, paths);
-
-                // Update C+(X) by removing A and R \ X
-                l.bitmap =
-                    l.bitmap.clone() - rhs - (RoaringBitmap::from_iter(paths.keys().cloned()) - x);
+                let rhs = RoaringBitmap::from(a); // A
+                let lhs = x - rhs.clone(); // X \ {A}
+
+                // Check validity of X \ {A} -> A
+                let violations = partitions
+                    .get(&lhs)
+                    .unwrap()
+                    .error(partitions.get(&rhs).unwrap());
+                if check_violations(violations, row_count, approximate, threshold) {
+                    fds.push(build_dependency(
+                        &lhs,
+                        a,
+                        paths,
+                        violations,
+                        row_count,
+                        approximate,
+                    ));
+
+                    // Update C+(X) by removing A and R \ X
+                    l.bitmap = l.bitmap.clone() - rhs - (&all - x);
+                }
             }
-        }
+
+            fds
+        })
+        .collect();
+
+    for fd in found {
+        sink.emit(fd);
+    }
+
+    if timed_out.into_inner() {
+        Completion::TimedOut
+    } else {
+        Completion::Complete
     }
 }
 
 fn initialize_cplus_for_level(level0: &Level, level1: &mut Level) {
     // This represents lines 1-2 of the COMPUTE_DEPENDENCIES procedure of TANE
-    for (x, l) in level1.iter_mut() {
+    level1.par_iter_mut().for_each(|(x, l)| {
         let mut first = true;
         for a in x {
             let level0_key = x - RoaringBitmap::from(a);
@@ -574,5 +1065,457 @@ fn initialize_cplus_for_level(level0: &Level, level1: &mut Level) {
                 l.bitmap = l.bitmap.clone() & old_cplus.bitmap.clone();
             }
         }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{PathFilter, PathPattern};
+    use crate::path::ArrayMode;
+    use crate::profile::{ProfileAction, ProfileOptions};
+    use crate::values::NullSemantics;
+
+    use std::sync::{Arc, Mutex};
+
+    use json::object;
+
+    #[test]
+    fn it_discovers_functional_dependencies() {
+        let docs = vec![
+            object! {a: 1, b: 1, c: 1},
+            object! {a: 2, b: 1, c: 2},
+            object! {a: 3, b: 2, c: 2},
+        ];
+        let fds = FdDiscovery::new().discover(docs);
+
+        let a_to_b = FunctionalDependency {
+            lhs: vec!["a".to_string()],
+            rhs: "b".to_string(),
+            error: None,
+            support: None,
+            value: None,
+            flagged: Vec::new(),
+        };
+        assert!(fds.contains(&a_to_b));
+        assert!(!fds.iter().any(|fd| fd.lhs == vec!["b"]));
+    }
+
+    #[test]
+    fn it_discovers_dependencies_in_many_documents() {
+        // More documents than could be indexed by pairs of documents
+        let docs = (0..100_000).map(|i| object! {a: i, b: i % 10});
+        let fds = FdDiscovery::new().discover(docs);
+
+        assert_eq!(fds.len(), 1);
+        assert_eq!(fds[0].lhs, vec!["a"]);
+        assert_eq!(fds[0].rhs, "b");
+    }
+
+    #[test]
+    fn it_requires_exact_dependencies_to_have_no_violations() {
+        let docs = (0..20).map(|i| object! {a: i / 2, b: i / 2 + usize::from(i == 0)});
+        let fds = FdDiscovery::new().threshold(0.0).discover(docs);
+
+        assert!(!fds.iter().any(|fd| fd.lhs == vec!["a"]));
+    }
+
+    #[test]
+    fn it_discovers_approximate_dependencies_within_the_threshold() {
+        let docs = || (0..20).map(|i| object! {a: i / 2, b: i / 2 + usize::from(i == 0)});
+
+        let fds = FdDiscovery::new()
+            .approximate(true)
+            .threshold(0.95)
+            .discover(docs());
+        let a_to_b = fds.iter().find(|fd| fd.lhs == vec!["a"]).unwrap();
+        assert_eq!(a_to_b.rhs, "b");
+        assert_eq!(a_to_b.error, Some(0.05));
+        assert_eq!(a_to_b.support, Some(19));
+
+        let fds = FdDiscovery::new()
+            .approximate(true)
+            .threshold(0.96)
+            .discover(docs());
+        assert!(!fds.iter().any(|fd| fd.lhs == vec!["a"]));
+    }
+
+    #[test]
+    fn it_limits_the_size_of_the_lhs() {
+        let docs = vec![
+            object! {a: 1, b: 1, c: 1},
+            object! {a: 1, b: 2, c: 2},
+            object! {a: 2, b: 1, c: 3},
+            object! {a: 2, b: 2, c: 4},
+        ];
+        let fds = FdDiscovery::new().discover(docs.clone());
+        assert!(fds.iter().any(|fd| fd.lhs.len() == 2));
+
+        let fds = FdDiscovery::new().max_lhs(Some(1)).discover(docs);
+        assert!(!fds.is_empty());
+        assert!(fds.iter().all(|fd| fd.lhs.len() <= 1));
+    }
+
+    #[test]
+    fn it_stops_when_the_candidate_budget_is_exhausted() {
+        let docs = vec![
+            object! {a: 1, b: 1, c: 1},
+            object! {a: 1, b: 2, c: 2},
+            object! {a: 2, b: 1, c: 3},
+            object! {a: 2, b: 2, c: 4},
+        ];
+        let mut fds = Vec::new();
+        let completion = FdDiscovery::new()
+            .max_candidates(Some(4))
+            .discover_into(docs, &mut fds);
+
+        assert_eq!(completion, Completion::CandidateLimit);
+        assert!(fds.iter().all(|fd| fd.lhs.len() <= 1));
+    }
+
+    #[test]
+    fn it_stops_within_a_level_at_the_deadline() {
+        let paths = HashMap::from([(0, Path::parse("a")), (1, Path::parse("b"))]);
+        let partitions = HashMap::from([
+            (RoaringBitmap::from(0), Partition::new([vec![0, 1]])),
+            (RoaringBitmap::from(1), Partition::new([vec![0, 1]])),
+            (
+                RoaringBitmap::from_iter([0, 1]),
+                Partition::new([vec![0, 1]]),
+            ),
+        ]);
+        let all = RoaringBitmap::from_iter([0, 1]);
+        let level0 = HashMap::from([
+            (
+                RoaringBitmap::from(0),
+                Element {
+                    bitmap: all.clone(),
+                    valid: true,
+                },
+            ),
+            (
+                RoaringBitmap::from(1),
+                Element {
+                    bitmap: all,
+                    valid: true,
+                },
+            ),
+        ]);
+        let level = || {
+            HashMap::from([(
+                RoaringBitmap::from_iter([0, 1]),
+                Element {
+                    bitmap: RoaringBitmap::new(),
+                    valid: true,
+                },
+            )])
+        };
+        let compute = |deadline| {
+            let mut fds = Vec::new();
+            let mut level1 = level();
+            let completion = compute_dependencies(
+                &level0,
+                &mut level1,
+                &partitions,
+                &paths,
+                3,
+                false,
+                1.0,
+                deadline,
+                &mut fds,
+            );
+            (completion, fds)
+        };
+
+        let (completion, fds) = compute(None);
+        assert_eq!(completion, Completion::Complete);
+        assert_eq!(fds.len(), 2);
+
+        let (completion, fds) = compute(Some(Instant::now()));
+        assert_eq!(completion, Completion::TimedOut);
+        assert!(fds.is_empty());
+
+        let mut fds = Vec::new();
+        let completion = prune(
+            &mut level(),
+            &partitions,
+            &paths,
+            3,
+            false,
+            1.0,
+            Some(Instant::now()),
+            &mut fds,
+        );
+        assert_eq!(completion, Completion::TimedOut);
+        assert!(fds.is_empty());
+    }
+
+    #[test]
+    fn it_discovers_constants() {
+        let docs = vec![
+            object! {a: 1, version: "1.0", b: 1},
+            object! {a: 2, version: "1.0", b: 1},
+            object! {a: 3, version: "1.0"},
+        ];
+
+        let fds = FdDiscovery::new().discover(docs.clone());
+        assert!(!fds.iter().any(|fd| fd.lhs.is_empty()));
+
+        let fds = FdDiscovery::new().constants(true).discover(docs.clone());
+        let constants: Vec<_> = fds.iter().filter(|fd| fd.lhs.is_empty()).collect();
+        assert_eq!(constants.len(), 1);
+        assert_eq!(constants[0].rhs, "version");
+        assert_eq!(constants[0].value, Some("1.0".into()));
+
+        let fds = FdDiscovery::new()
+            .constants(true)
+            .approximate(true)
+            .threshold(0.6)
+            .discover(docs);
+        let b = fds.iter().find(|fd| fd.lhs.is_empty() && fd.rhs == "b");
+        assert_eq!(b.unwrap().support, Some(2));
+    }
+
+    #[test]
+    fn it_discovers_constants_in_a_single_document() {
+        let fds = FdDiscovery::new()
+            .constants(true)
+            .discover(vec![object! {a: 1, b: "x"}]);
+
+        assert_eq!(fds.len(), 2);
+        assert!(fds.iter().all(|fd| fd.lhs.is_empty()));
+    }
+
+    #[test]
+    fn it_compares_nulls_based_on_semantics() {
+        let docs = vec![
+            object! {a: 1, b: null},
+            object! {a: 1, b: null},
+            object! {a: 2, b: 1},
+        ];
+        let a_to_b = |nulls| {
+            FdDiscovery::new()
+                .values(ValueOptions {
+                    nulls,
+                    ..ValueOptions::default()
+                })
+                .discover(docs.clone())
+                .iter()
+                .any(|fd| fd.lhs == vec!["a"] && fd.rhs == "b")
+        };
+
+        assert!(a_to_b(NullSemantics::NullEqualsNull));
+        assert!(!a_to_b(NullSemantics::NullNotEqualsNull));
+    }
+
+    #[test]
+    fn it_treats_empty_values_the_same_when_static() {
+        let docs = vec![
+            object! {a: {}, b: 1},
+            object! {a: [], b: 2},
+            object! {a: "", b: 3},
+        ];
+        let discover = |keep_empty, dynamic| {
+            FdDiscovery::new()
+                .dynamic(dynamic)
+                .values(ValueOptions {
+                    keep_empty,
+                    ..ValueOptions::default()
+                })
+                .discover(docs.clone())
+        };
+
+        for keep_empty in [true, false] {
+            let fds = discover(keep_empty, true);
+            assert_eq!(fds, discover(keep_empty, false));
+            assert_eq!(fds.iter().any(|fd| fd.lhs == vec!["a"]), keep_empty);
+        }
+    }
+
+    #[test]
+    fn it_only_considers_filtered_paths() {
+        let docs = vec![
+            object! {a: 1, b: 1, _meta: {c: 1}},
+            object! {a: 2, b: 2, _meta: {c: 2}},
+            object! {a: 3, b: 3, _meta: {c: 3}},
+        ];
+        let fds = FdDiscovery::new()
+            .values(ValueOptions {
+                paths: PathFilter {
+                    include: vec![],
+                    exclude: vec![PathPattern::new("_meta")],
+                },
+                ..ValueOptions::default()
+            })
+            .discover(docs);
+
+        assert_eq!(fds.len(), 2);
+        assert!(fds.iter().all(|fd| !fd.rhs.starts_with("_meta")));
+    }
+
+    #[test]
+    fn it_excludes_paths_based_on_their_profile() {
+        let docs = (0..10).map(|i| object! {id: format!("{:08}", i), a: i % 2, b: i % 2});
+        let fds = FdDiscovery::new()
+            .values(ValueOptions {
+                profile: ProfileOptions {
+                    max_distinct_ratio: Some(0.5),
+                    ..ProfileOptions::default()
+                },
+                ..ValueOptions::default()
+            })
+            .discover(docs);
+
+        assert_eq!(fds.len(), 2);
+        assert!(fds.iter().all(|fd| !fd.lhs.contains(&"id".to_string())));
+    }
+
+    #[test]
+    fn it_flags_dependencies_on_paths_failing_their_profile() {
+        let docs = (0..10).map(|i| object! {id: format!("{:08}", i), a: i % 2});
+        let fds = FdDiscovery::new()
+            .values(ValueOptions {
+                profile: ProfileOptions {
+                    max_distinct_ratio: Some(0.5),
+                    action: ProfileAction::Flag,
+                    ..ProfileOptions::default()
+                },
+                ..ValueOptions::default()
+            })
+            .discover(docs);
+
+        let id_to_a = fds.iter().find(|fd| fd.lhs == vec!["id"]).unwrap();
+        assert_eq!(id_to_a.flagged, vec!["id"]);
+        assert!(fds
+            .iter()
+            .filter(|fd| fd.lhs != vec!["id"])
+            .all(|fd| fd.flagged.is_empty()));
+    }
+
+    #[test]
+    fn it_distinguishes_keys_containing_dots() {
+        let docs = vec![
+            object! {"a.b": 1, a: {b: 1}},
+            object! {"a.b": 1, a: {b: 2}},
+            object! {"a.b": 2, a: {b: 3}},
+        ];
+        let fds = FdDiscovery::new().discover(docs);
+
+        assert_eq!(fds.len(), 1);
+        assert_eq!(fds[0].lhs, vec!["a.b"]);
+        assert_eq!(fds[0].rhs, "[\"a.b\"]");
+    }
+
+    #[test]
+    fn it_indexes_tuples_in_auto_array_mode() {
+        let docs = vec![
+            object! {point: [1, 5], tags: [1]},
+            object! {point: [1, 6], tags: [2, 3]},
+            object! {point: [2, 7], tags: [4]},
+        ];
+        let discover = |arrays, dynamic| {
+            FdDiscovery::new()
+                .dynamic(dynamic)
+                .values(ValueOptions {
+                    arrays,
+                    ..ValueOptions::default()
+                })
+                .discover(docs.clone())
+        };
+
+        for dynamic in [true, false] {
+            let fds = discover(ArrayMode::Auto, dynamic);
+            assert!(fds
+                .iter()
+                .any(|fd| fd.lhs == vec!["point[1]"] && fd.rhs == "point[0]"));
+            assert!(fds.iter().any(|fd| fd.lhs == vec!["tags[*]"]));
+            assert!(fds
+                .iter()
+                .all(|fd| fd.rhs != "point[*]" && fd.rhs != "tags[0]"));
+        }
+
+        let fds = discover(ArrayMode::Wildcard, true);
+        assert!(fds.iter().all(|fd| fd.rhs != "point[0]"));
+    }
+
+    #[test]
+    fn it_filters_indexed_array_elements() {
+        let docs = vec![
+            object! {a: 1, tags: [1, 2]},
+            object! {a: 2, tags: [1, 3]},
+            object! {a: 3, tags: [2, 4]},
+        ];
+        let fds = FdDiscovery::new()
+            .values(ValueOptions {
+                arrays: ArrayMode::Indexed,
+                paths: PathFilter {
+                    include: vec![],
+                    exclude: vec![PathPattern::new("tags[*]")],
+                },
+                ..ValueOptions::default()
+            })
+            .discover(docs);
+
+        assert!(fds.is_empty());
+    }
+
+    #[test]
+    fn it_discovers_dependencies_within_a_scope() {
+        let docs = vec![
+            object! {order: 1, items: [{sku: "a", price: 1}, {sku: "b", price: 2}]},
+            object! {order: 2, items: [{sku: "a", price: 2}]},
+            object! {order: 3, items: [{sku: "c", price: 3}]},
+        ];
+        let sku_to_price = |fd: &FunctionalDependency| {
+            fd.lhs == vec!["items[*].sku"] && fd.rhs == "items[*].price"
+        };
+
+        // Documents share a price when any of their items do
+        let fds = FdDiscovery::new().discover(docs.clone());
+        assert!(fds.iter().any(sku_to_price));
+
+        let fds = FdDiscovery::new()
+            .scope(Some(Scope::new("items[*]")))
+            .discover(docs.clone());
+        assert!(!fds.iter().any(sku_to_price));
+        assert!(fds.iter().all(|fd| fd.rhs != "order"));
+
+        let fds = FdDiscovery::new()
+            .scope(Some(Scope::new("items[*]").inherit(true)))
+            .discover(docs);
+        assert!(fds.iter().any(|fd| fd.rhs == "order"));
+    }
+
+    #[test]
+    fn it_identifies_documents_up_to_the_limit() {
+        assert_eq!(row_id(MAX_DOCUMENTS - 1).unwrap(), u32::MAX - 1);
+        assert!(matches!(
+            row_id(MAX_DOCUMENTS),
+            Err(Error::TooManyDocuments { .. })
+        ));
+    }
+
+    #[test]
+    fn it_reports_progress_as_diagnostics() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let diagnostics = {
+            let reported = reported.clone();
+            Diagnostics::new(move |diagnostic| reported.lock().unwrap().push(diagnostic.clone()))
+        };
+        let docs = vec![object! {a: 1, b: 1}, object! {a: 2, b: 1}];
+        FdDiscovery::new().diagnostics(diagnostics).discover(docs);
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(
+            reported[0],
+            Diagnostic::Progress("Starting level 1...".to_string())
+        );
+    }
+
+    #[test]
+    fn it_discovers_nothing_without_input() {
+        let fds = FdDiscovery::new().discover(vec![]);
+
+        assert!(fds.is_empty());
     }
 }
@@ -0,0 +1,171 @@
+//! Selection of paths using glob patterns
+//!
+//! Patterns use the same syntax as paths such as `a.b[*].c` and are
+//! matched one segment at a time. A `*` matches any part of a single
+//! key (including quoted keys such as `["a.b"]`), a `**` segment
+//! matches any number of segments, and `[*]` matches any element of an
+//! array, whether written as `[*]` or by position as `[0]`. A pattern
+//! which matches a path also matches every path nested below it, so
+//! `_meta` selects `_meta.created`.
+
+use crate::path::{Path, Segment};
+
+/// A pattern matching a single segment of a path
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SegmentPattern {
+    /// A key where `*` matches any characters
+    Key(Vec<char>),
+    /// Any number of segments, including none
+    Any,
+    /// The element of an array at a position
+    Index(usize),
+    /// Any element of an array
+    Element,
+}
+
+impl SegmentPattern {
+    fn matches(&self, segment: &Segment) -> bool {
+        match (self, segment) {
+            (SegmentPattern::Key(glob), Segment::Key(key)) => {
+                matches_key(glob, &key.chars().collect::<Vec<_>>())
+            }
+            (SegmentPattern::Index(i), Segment::Index(j)) => i == j,
+            (SegmentPattern::Element, Segment::Element | Segment::Index(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A glob pattern over paths
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathPattern {
+    segments: Vec<SegmentPattern>,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Self {
+        let segments = Path::parse(pattern)
+            .segments()
+            .iter()
+            .map(|segment| match segment {
+                Segment::Key(key) if key == "**" => SegmentPattern::Any,
+                Segment::Key(key) => SegmentPattern::Key(key.chars().collect()),
+                Segment::Index(index) => SegmentPattern::Index(*index),
+                Segment::Element => SegmentPattern::Element,
+            })
+            .collect();
+
+        PathPattern { segments }
+    }
+
+    /// Check if the pattern matches the path or any path containing it
+    pub fn matches(&self, path: &Path) -> bool {
+        !self.segments.is_empty() && matches_prefix(&self.segments, path.segments())
+    }
+}
+
+/// Check if the patterns match the segments at the start of a path
+fn matches_prefix(patterns: &[SegmentPattern], segments: &[Segment]) -> bool {
+    match patterns.split_first() {
+        None => true,
+        Some((SegmentPattern::Any, rest)) => {
+            (0..=segments.len()).any(|i| matches_prefix(rest, &segments[i..]))
+        }
+        Some((pattern, rest)) => segments.split_first().is_some_and(|(segment, after)| {
+            pattern.matches(segment) && matches_prefix(rest, after)
+        }),
+    }
+}
+
+/// Check if a key matches a glob where `*` matches any characters
+fn matches_key(glob: &[char], key: &[char]) -> bool {
+    match glob.split_first() {
+        None => key.is_empty(),
+        Some(('*', rest)) => (0..=key.len()).any(|i| matches_key(rest, &key[i..])),
+        Some((c, rest)) => key.first() == Some(c) && matches_key(rest, &key[1..]),
+    }
+}
+
+/// Decides which paths are considered during discovery
+///
+/// A path is considered if it matches any of the included patterns (or
+/// if there are none) and does not match any of the excluded patterns.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathFilter {
+    pub include: Vec<PathPattern>,
+    pub exclude: Vec<PathPattern>,
+}
+
+impl PathFilter {
+    /// Check if a path and every path below it are excluded
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|pattern| pattern.matches(path))
+    }
+
+    /// Check if the values at a path are considered
+    pub fn allows(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(path)))
+            && !self.is_excluded(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_within_a_segment() {
+        let pattern = PathPattern::new("a.*_id");
+
+        assert!(pattern.matches(&Path::parse("a.user_id")));
+        assert!(pattern.matches(&Path::parse("a.user_id.value")));
+        assert!(!pattern.matches(&Path::parse("a.b.user_id")));
+        assert!(!pattern.matches(&Path::parse("a.user_ids")));
+    }
+
+    #[test]
+    fn it_matches_across_segments() {
+        let pattern = PathPattern::new("**.description");
+
+        assert!(pattern.matches(&Path::parse("a.description")));
+        assert!(pattern.matches(&Path::parse("a.b[*].description")));
+        assert!(pattern.matches(&Path::parse("description")));
+        assert!(!pattern.matches(&Path::parse("descriptions")));
+    }
+
+    #[test]
+    fn it_matches_quoted_keys() {
+        assert!(PathPattern::new("a.*").matches(&Path::parse("a[\"b.c\"]")));
+        assert!(PathPattern::new("a[\"b.*\"]").matches(&Path::parse("a[\"b.c\"].d")));
+        assert!(!PathPattern::new("a[\"b").matches(&Path::parse("a[\"b.c\"]")));
+        assert!(!PathPattern::new("a.b").matches(&Path::parse("a[\"b.c\"]")));
+    }
+
+    #[test]
+    fn it_matches_array_elements() {
+        let pattern = PathPattern::new("tags[*]");
+
+        assert!(pattern.matches(&Path::parse("tags[*]")));
+        assert!(pattern.matches(&Path::parse("tags[*].name")));
+        assert!(!pattern.matches(&Path::parse("tags")));
+        assert!(!pattern.matches(&Path::parse("tagsx")));
+        assert!(pattern.matches(&Path::parse("tags[0].name")));
+        assert!(!PathPattern::new("tags[0]").matches(&Path::parse("tags[1]")));
+        assert!(PathPattern::new("tag*[*]").matches(&Path::parse("tags[*]")));
+        assert!(!PathPattern::new("tags*.name").matches(&Path::parse("tags[*].name")));
+    }
+
+    #[test]
+    fn it_filters_paths() {
+        let filter = PathFilter {
+            include: vec![PathPattern::new("user")],
+            exclude: vec![PathPattern::new("**.password")],
+        };
+
+        assert!(filter.allows(&Path::parse("user.name")));
+        assert!(!filter.allows(&Path::parse("user.password")));
+        assert!(!filter.allows(&Path::parse("user.password.hash")));
+        assert!(!filter.allows(&Path::parse("users")));
+        assert!(PathFilter::default().allows(&Path::parse("anything")));
+    }
+}
@@ -1,78 +1,307 @@
 //! Functions for flattening nested JSON into simple unnested key-value objects
 
+use clap::ValueEnum;
 use itertools::Itertools;
 use json::JsonValue;
 
-/// Add everything in the `src` object into `dest`
-fn merge_into<'a>(
-    dest: &'a mut json::object::Object,
-    src: &json::object::Object,
-) -> &'a json::object::Object {
-    for (key, value) in src.iter() {
-        dest[key] = value.clone();
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::error::Error;
+use crate::input::Origin;
+use crate::path::{self, ArrayMode, Path, Segment};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+/// What happens when a document flattens into too many values
+pub enum OnExpansion {
+    #[default]
+    Fail,
+    Truncate,
+    Sample,
+}
+
+/// A limit on the number of values each document is flattened into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExpansionLimit {
+    pub rows: u64,
+    pub action: OnExpansion,
+}
+
+/// The unnested values produced from a document along with their paths
+pub(crate) type Row = Vec<(Path, JsonValue)>;
+
+/// Create the product of groups of rows and
+/// then combine everything in each element of the product
+fn merge_product(groups: Vec<Vec<Row>>) -> impl Iterator<Item = Row> {
+    groups
+        .into_iter()
+        .multi_cartesian_product()
+        .map(|rows| rows.concat())
+}
+
+/// Write the paths of a row to create an unnested object
+fn row_object(row: Row) -> JsonValue {
+    let mut obj = json::object::Object::new();
+    for (path, value) in row {
+        obj.insert(&path.to_string(), value);
     }
 
-    dest
+    JsonValue::Object(obj)
 }
 
 /// Flatten a JSON document into an iterator of unnested values
 pub fn flatten_json(json: &JsonValue) -> impl Iterator<Item = JsonValue> + '_ {
-    flatten_json_with_path(json, "".to_string())
+    flatten_json_with_mode(json, ArrayMode::Wildcard)
+}
+
+/// Flatten a JSON document into an iterator of unnested values
+/// using a particular representation for array elements
+///
+/// Indexed elements are combined into the same value like the keys
+/// of an object. In auto mode, short arrays produce both indexed and
+/// wildcard paths since whether they are tuples depends on the arrays
+/// at the same path in other documents.
+pub fn flatten_json_with_mode(
+    json: &JsonValue,
+    mode: ArrayMode,
+) -> impl Iterator<Item = JsonValue> + '_ {
+    flatten_json_with_path(json, Path::default(), mode).map(row_object)
+}
+
+/// Flatten a document unless it produces more values than a limit,
+/// in which case the document is rejected or only some values are used
+///
+/// The number of values is calculated before any are produced. The
+/// origin of the document is used to report errors and to report
+/// documents which are truncated or sampled as diagnostics.
+pub fn flatten_json_limited<'a>(
+    json: &'a JsonValue,
+    mode: ArrayMode,
+    limit: Option<ExpansionLimit>,
+    document: &Origin,
+    diagnostics: &Diagnostics,
+) -> Result<impl Iterator<Item = JsonValue> + 'a, Error> {
+    let parts = vec![(Path::default(), json)];
+    Ok(flatten_rows_limited(parts, mode, limit, document, diagnostics)?.map(row_object))
+}
+
+/// Flatten values which are each at a path within a document into rows
+/// of paths and values with a limit as in [`flatten_json_limited`]
+pub(crate) fn flatten_rows_limited<'a>(
+    parts: Vec<(Path, &'a JsonValue)>,
+    mode: ArrayMode,
+    limit: Option<ExpansionLimit>,
+    document: &Origin,
+    diagnostics: &Diagnostics,
+) -> Result<Box<dyn Iterator<Item = Row> + 'a>, Error> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return Ok(flatten_parts(parts, mode)),
+    };
+
+    let rows = parts
+        .iter()
+        .map(|(_, json)| expansion_size(json, mode))
+        .fold(1, u64::saturating_mul);
+    if rows <= limit.rows {
+        return Ok(flatten_parts(parts, mode));
+    }
+
+    match limit.action {
+        OnExpansion::Fail => Err(Error::TooManyRows {
+            document: document.clone(),
+            rows,
+            limit: limit.rows,
+        }),
+        OnExpansion::Truncate => {
+            diagnostics.report(Diagnostic::Warning(format!(
+                "Truncating {} from {} rows to {}",
+                document, rows, limit.rows
+            )));
+            let rows = flatten_parts(parts, mode);
+            Ok(Box::new(rows.take(limit.rows as usize)))
+        }
+        OnExpansion::Sample => {
+            diagnostics.report(Diagnostic::Warning(format!(
+                "Sampling {} of {} rows from {}",
+                limit.rows, rows, document
+            )));
+
+            // Pick evenly spaced rows without producing the others
+            let sample = (0..limit.rows).map(move |i| {
+                let index = (i as u128 * rows as u128 / limit.rows as u128) as u64;
+                parts_row_at(&parts, mode, index)
+            });
+            Ok(Box::new(sample))
+        }
+    }
+}
+
+/// Whether the elements of an array produce indexed paths and wildcard paths
+fn array_groups(mode: ArrayMode, len: usize) -> (bool, bool) {
+    let indexed =
+        mode == ArrayMode::Indexed || (mode == ArrayMode::Auto && len <= path::MAX_TUPLE_LENGTH);
+    (indexed, mode != ArrayMode::Indexed)
+}
+
+/// Count the unnested values produced by flattening a
+/// JSON value, saturating at the largest `u64`
+pub fn expansion_size(json: &JsonValue, mode: ArrayMode) -> u64 {
+    match json {
+        JsonValue::Object(obj) => obj
+            .iter()
+            .map(|(_, v)| expansion_size(v, mode))
+            .fold(1, u64::saturating_mul),
+        JsonValue::Array(arr) if !arr.is_empty() => {
+            let sizes: Vec<_> = arr.iter().map(|a| expansion_size(a, mode)).collect();
+            let (indexed, wildcard) = array_groups(mode, arr.len());
+
+            let mut size: u64 = 1;
+            if indexed {
+                size = sizes.iter().fold(size, |a, b| a.saturating_mul(*b));
+            }
+            if wildcard {
+                size = size.saturating_mul(sizes.iter().fold(0, |a: u64, b| a.saturating_add(*b)));
+            }
+
+            size
+        }
+        _ => 1,
+    }
+}
+
+/// Flatten values at several paths into the product of their rows
+fn flatten_parts<'a>(
+    mut parts: Vec<(Path, &'a JsonValue)>,
+    mode: ArrayMode,
+) -> Box<dyn Iterator<Item = Row> + 'a> {
+    if parts.len() == 1 {
+        let (path, json) = parts.pop().unwrap();
+        return flatten_json_with_path(json, path, mode);
+    }
+
+    let groups = parts
         .into_iter()
-        .map(JsonValue::Object)
+        .map(|(path, json)| flatten_json_with_path(json, path, mode).collect())
+        .collect();
+    Box::new(merge_product(groups))
+}
+
+/// Produce a single row from flattening values at several paths,
+/// in the same order as [`flatten_parts`], without the others
+fn parts_row_at(parts: &[(Path, &JsonValue)], mode: ArrayMode, mut index: u64) -> Row {
+    // Find the position in each part with the last changing fastest
+    let mut children: Vec<_> = parts
+        .iter()
+        .rev()
+        .map(|(path, json)| {
+            let size = expansion_size(json, mode);
+            let child_index = index % size;
+            index /= size;
+            (path, json, child_index)
+        })
+        .collect();
+    children.reverse();
+
+    children
+        .into_iter()
+        .flat_map(|(path, json, i)| row_at(json, path.clone(), mode, i))
+        .collect()
+}
+
+/// Produce a single value from flattening a JSON value, in the
+/// same order as [`flatten_json_with_mode`], without the others
+fn row_at(json: &JsonValue, path: Path, mode: ArrayMode, mut index: u64) -> Row {
+    match json {
+        JsonValue::Object(obj) if !obj.is_empty() => {
+            let parts: Vec<_> = obj.iter().map(|(k, v)| (path.key(k), v)).collect();
+            parts_row_at(&parts, mode, index)
+        }
+        JsonValue::Array(arr) if !arr.is_empty() => {
+            let sizes: Vec<_> = arr.iter().map(|a| expansion_size(a, mode)).collect();
+            let (indexed, wildcard) = array_groups(mode, arr.len());
+            let mut row = Row::new();
+
+            // All wildcard elements together are the last group
+            if wildcard {
+                let total = sizes.iter().fold(0, |a: u64, b| a.saturating_add(*b));
+                let mut wildcard_index = index % total;
+                index /= total;
+
+                for (a, size) in arr.iter().zip(&sizes) {
+                    if wildcard_index < *size {
+                        row = row_at(a, path.child(Segment::Element), mode, wildcard_index);
+                        break;
+                    }
+                    wildcard_index -= size;
+                }
+            }
+
+            if indexed {
+                for (i, (a, size)) in arr.iter().zip(&sizes).enumerate().rev() {
+                    let new_path = path.child(Segment::Index(i));
+                    row.extend(row_at(a, new_path, mode, index % size));
+                    index /= size;
+                }
+            }
+
+            row
+        }
+        // Empty containers are kept as values like scalars
+        _ => vec![(path, json.clone())],
+    }
 }
 
 /// Flatten a JSON value with a particular prefix
 fn flatten_json_with_path(
     json: &JsonValue,
-    path: String,
-) -> Box<dyn Iterator<Item = json::object::Object> + '_> {
+    path: Path,
+    mode: ArrayMode,
+) -> Box<dyn Iterator<Item = Row> + '_> {
     match json {
         JsonValue::Object(obj) => {
             if obj.is_empty() {
-                let mut new_obj = json::object::Object::new();
-                new_obj[path] = "".into();
-                Box::new(vec![new_obj].into_iter())
+                Box::new(std::iter::once(vec![(path, json.clone())]))
             } else {
-                // Add a prefix if necessary
-                let prefix = if path.is_empty() {
-                    path
-                } else {
-                    format!("{}.", path)
-                };
-
-                // Generate JSON objects for each dictionary element
-                let dict_jsons = obj.iter().map(|(k, v)| {
-                    flatten_json_with_path(v, prefix.clone() + k).collect::<Vec<_>>()
-                });
-
-                // Create the product of each of these elements
-                // and then combine everything in the product
-                let product = dict_jsons.multi_cartesian_product();
-                Box::new(product.map(|dicts| {
-                    dicts.iter().fold(json::object::Object::new(), |mut a, b| {
-                        merge_into(&mut a, b).clone()
-                    })
-                }))
+                // Generate rows for each dictionary element
+                let dict_rows = obj
+                    .iter()
+                    .map(|(k, v)| flatten_json_with_path(v, path.key(k), mode).collect::<Vec<_>>());
+
+                Box::new(merge_product(dict_rows.collect()))
             }
         }
         JsonValue::Array(arr) => {
             if arr.is_empty() {
-                let mut new_obj = json::object::Object::new();
-                new_obj[path] = "".into();
-                Box::new(vec![new_obj].into_iter())
-            } else {
+                Box::new(std::iter::once(vec![(path, json.clone())]))
+            } else if mode == ArrayMode::Wildcard {
+                let new_path = path.child(Segment::Element);
                 Box::new(
                     arr.iter()
-                        .flat_map(move |a| flatten_json_with_path(a, format!("{}[*]", path))),
+                        .flat_map(move |a| flatten_json_with_path(a, new_path.clone(), mode)),
                 )
+            } else {
+                // Indexed elements are each a separate group while
+                // all wildcard elements together form a single group
+                let mut indexed = Vec::new();
+                let mut wildcard = Vec::new();
+                for (i, a) in arr.iter().enumerate() {
+                    for new_path in path::element_paths(mode, &path, i, arr.len()) {
+                        let is_element = new_path.is_element();
+                        let flat = flatten_json_with_path(a, new_path, mode);
+                        if is_element {
+                            wildcard.extend(flat);
+                        } else {
+                            indexed.push(flat.collect());
+                        }
+                    }
+                }
+                if !wildcard.is_empty() {
+                    indexed.push(wildcard);
+                }
+
+                Box::new(merge_product(indexed))
             }
         }
-        _ => {
-            let mut new_obj = json::object::Object::new();
-            new_obj[path] = json.clone();
-            Box::new(vec![new_obj].into_iter())
-        }
+        _ => Box::new(std::iter::once(vec![(path, json.clone())])),
     }
 }
 
@@ -100,4 +329,100 @@ mod tests {
         assert_eq!(flat.len(), 1);
         assert_eq!(flat[0]["foo.bar"], 1);
     }
+
+    #[test]
+    fn it_flattens_indexed_arrays() {
+        let array_obj = object! {foo: [1, 2]};
+        let flat: Vec<_> = flatten_json_with_mode(&array_obj, ArrayMode::Indexed).collect();
+
+        assert_eq!(flat.len(), 1);
+        assert_eq!(flat[0]["foo[0]"], 1);
+        assert_eq!(flat[0]["foo[1]"], 2);
+
+        let flat: Vec<_> = flatten_json_with_mode(&array_obj, ArrayMode::Auto).collect();
+        assert_eq!(flat.len(), 2);
+        assert_eq!(flat[1]["foo[0]"], 1);
+        assert_eq!(flat[1]["foo[*]"], 2);
+    }
+
+    #[test]
+    fn it_keeps_empty_containers_as_values() {
+        let obj = object! {a: {}, b: [], c: ""};
+        let flat: Vec<_> = flatten_json(&obj).collect();
+
+        assert_eq!(flat, vec![obj]);
+    }
+
+    #[test]
+    fn it_calculates_the_expansion_size() {
+        let obj = object! {a: [1, 2, 3], b: [{c: [1, 2]}, {c: 3}], d: 1, e: []};
+
+        for mode in [ArrayMode::Wildcard, ArrayMode::Indexed, ArrayMode::Auto] {
+            let size = flatten_json_with_mode(&obj, mode).count() as u64;
+            assert_eq!(expansion_size(&obj, mode), size);
+        }
+        assert_eq!(expansion_size(&obj, ArrayMode::Wildcard), 9);
+    }
+
+    #[test]
+    fn it_limits_the_expansion_of_documents() {
+        let obj = object! {a: [1, 2, 3], b: [4, 5, 6]};
+        let limit = |action| Some(ExpansionLimit { rows: 4, action });
+        let origin = Origin::Index(2);
+
+        let result = flatten_json_limited(
+            &obj,
+            ArrayMode::Wildcard,
+            limit(OnExpansion::Fail),
+            &origin,
+            &Diagnostics::default(),
+        );
+        assert!(matches!(
+            result.err(),
+            Some(Error::TooManyRows {
+                document: Origin::Index(2),
+                rows: 9,
+                limit: 4
+            })
+        ));
+
+        let all: Vec<_> = flatten_json(&obj).collect();
+        let truncated: Vec<_> = flatten_json_limited(
+            &obj,
+            ArrayMode::Wildcard,
+            limit(OnExpansion::Truncate),
+            &origin,
+            &Diagnostics::default(),
+        )
+        .unwrap()
+        .collect();
+        assert_eq!(truncated, all[..4]);
+
+        for mode in [ArrayMode::Wildcard, ArrayMode::Auto] {
+            let all: Vec<_> = flatten_json_with_mode(&obj, mode).collect();
+            let sampled: Vec<_> = flatten_json_limited(
+                &obj,
+                mode,
+                limit(OnExpansion::Sample),
+                &origin,
+                &Diagnostics::default(),
+            )
+            .unwrap()
+            .collect();
+            let step = all.len() / 4;
+            assert_eq!(sampled.len(), 4);
+            assert_eq!(sampled[0], all[0]);
+            assert_eq!(sampled[1], all[step]);
+        }
+    }
+
+    #[test]
+    fn it_quotes_keys_containing_dots() {
+        let obj = object! {"foo.bar": 1, foo: {bar: 2}};
+        let flat: Vec<_> = flatten_json(&obj).collect();
+
+        assert_eq!(flat.len(), 1);
+        assert_eq!(flat[0]["[\"foo.bar\"]"], 1);
+        assert_eq!(flat[0]["foo.bar"], 2);
+    }
 }
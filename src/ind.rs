@@ -1,56 +1,125 @@
-use crate::flatten::flatten_json;
+use crate::diagnostics::Diagnostics;
+use crate::error::Error;
+use crate::flatten::flatten_rows_limited;
+use crate::input::{locate, Document};
+use crate::output::Dependency;
+use crate::path::{self, Path, PathStyle};
+use crate::profile::{flagged_paths, screen_paths};
+use crate::scope::{scoped_documents, Scope};
+use crate::values::{ValueOptions, Values};
+
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
-use std::collections::HashMap;
-use std::io;
-use std::time::{Duration, Instant};
-
-use clap::Args;
 use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
+use json::object;
 use rayon::prelude::*;
 use roaring::bitmap::RoaringBitmap;
 
+/// An inclusion dependency where the values of
+/// the dependent path are contained in the referenced path
+#[derive(Clone, Debug, PartialEq)]
+pub struct InclusionDependency {
+    pub dependent: String,
+    pub referenced: String,
+    /// Fraction of dependent values found in the referenced path
+    pub coverage: f64,
+    pub dependent_distinct: u64,
+    pub referenced_distinct: u64,
+    /// Paths in the dependency which failed the profiling thresholds
+    pub flagged: Vec<String>,
+}
+
+impl InclusionDependency {
+    fn new(dependent: &Path, referenced: &Path, intersection: u64, values: (u64, u64)) -> Self {
+        InclusionDependency {
+            dependent: dependent.to_string(),
+            referenced: referenced.to_string(),
+            coverage: intersection as f64 / values.0 as f64,
+            dependent_distinct: values.0,
+            referenced_distinct: values.1,
+            flagged: Vec::new(),
+        }
+    }
+}
+
+impl Dependency for InclusionDependency {
+    fn to_json(&self) -> json::JsonValue {
+        let mut obj = object! {
+            dependent: self.dependent.clone(),
+            referenced: self.referenced.clone(),
+            coverage: self.coverage,
+            dependent_distinct: self.dependent_distinct,
+            referenced_distinct: self.referenced_distinct,
+        };
+        if !self.flagged.is_empty() {
+            obj["flagged"] = self.flagged.clone().into();
+        }
+
+        obj
+    }
+
+    fn to_text(&self) -> String {
+        format!("{:?}", (&self.dependent, &self.referenced))
+    }
+
+    fn with_path_style(self, style: PathStyle) -> Self {
+        InclusionDependency {
+            dependent: path::format(&self.dependent, style),
+            referenced: path::format(&self.referenced, style),
+            flagged: self
+                .flagged
+                .iter()
+                .map(|p| path::format(p, style))
+                .collect(),
+            ..self
+        }
+    }
+}
+
+/// The maximum number of distinct values which can be processed
+///
+/// Values are identified by a `u32` within each path's bitmap.
+pub const MAX_VALUES: u64 = u32::MAX as u64 + 1;
+
+/// Stop if more distinct values have been seen than can be identified
+fn check_value_count(count: usize) -> Result<(), Error> {
+    if count as u64 <= MAX_VALUES {
+        Ok(())
+    } else {
+        Err(Error::TooManyValues { limit: MAX_VALUES })
+    }
+}
+
 fn collect_values(
-    values: &mut HashMap<String, RoaringBitmap>,
-    all_values: &mut HashMap<String, usize>,
-    path: &str,
+    values: &mut HashMap<Path, RoaringBitmap>,
+    all_values: &mut Values,
+    path: &Path,
     value: &json::JsonValue,
 ) {
-    if value.is_object() {
+    if all_values.is_excluded(path) {
+        return;
+    }
+
+    if value.is_object() && all_values.is_nested(value) {
         // Traverse all keys in a dictionary adding a dot to the path
         for (dict_key, dict_value) in value.entries() {
-            let mut new_path: String = path.to_owned();
-
-            if !new_path.is_empty() {
-                new_path.push('.');
-            }
-            new_path.push_str(dict_key);
-            collect_values(values, all_values, &new_path, dict_value);
+            collect_values(values, all_values, &path.key(dict_key), dict_value);
         }
-    } else if value.is_array() {
+    } else if value.is_array() && all_values.is_nested(value) {
         // Loop through all array elements and add [] to the path
-        for list_value in value.members() {
-            let mut new_path: String = path.to_owned();
-            new_path.push_str("[*]");
-            collect_values(values, all_values, &new_path, list_value);
-        }
-    } else if !value.is_null() && (!value.is_string() || !value.is_empty()) {
-        let str_value = value.dump();
-        let str_index: usize = match all_values.get(&str_value) {
-            Some(index) => *index,
-            None => {
-                let new_index = all_values.len();
-                all_values.insert(str_value.clone(), new_index);
-
-                new_index
+        for (i, list_value) in value.members().enumerate() {
+            for new_path in all_values.element_paths(path, i, value.len()) {
+                collect_values(values, all_values, &new_path, list_value);
             }
-        };
-
+        }
+    } else if let Some(str_index) = all_values.intern(path, value) {
         if !values.contains_key(path) {
             // Create a new set to represent values with this path
             let mut set = RoaringBitmap::new();
             set.insert(str_index as u32);
-            values.insert(path.to_owned(), set);
+            values.insert(path.clone(), set);
         } else {
             // Add this value to those observed at this path
             values.get_mut(path).unwrap().insert(str_index as u32);
@@ -58,139 +127,472 @@ fn collect_values(
     }
 }
 
-#[derive(Args, Debug)]
-/// Inclusion dependency discovery
-pub struct INDArgs {
-    #[clap(short, long, default_value_t = 0.9)]
-    /// Threshold for approximate discovery
+/// Builder for inclusion dependency discovery
+#[derive(Clone, Debug)]
+pub struct IndDiscovery {
     threshold: f64,
-
-    #[clap(short, long, action=clap::ArgAction::SetTrue, default_value_t = false)]
-    /// Enable approximate discovery
     approximate: bool,
-
-    #[clap(short='s', long="static", action=clap::ArgAction::SetFalse, default_value_t = true)]
-    /// Use static discovery
     dynamic: bool,
+    cross_collection: bool,
+    progress: bool,
+    diagnostics: Diagnostics,
+    values: ValueOptions,
+    scope: Option<Scope>,
+}
+
+impl Default for IndDiscovery {
+    fn default() -> Self {
+        IndDiscovery {
+            threshold: 0.9,
+            approximate: false,
+            dynamic: true,
+            cross_collection: false,
+            progress: false,
+            diagnostics: Diagnostics::default(),
+            values: ValueOptions::default(),
+            scope: None,
+        }
+    }
 }
 
-pub fn discover(args: INDArgs) {
-    let mut values: HashMap<String, RoaringBitmap> = HashMap::new();
-    let mut all_values: HashMap<String, usize> = HashMap::new();
+impl IndDiscovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    // // Initialize spinner
-    let mut spinner = ProgressBar::new_spinner().with_message("Reading input…");
-    spinner.enable_steady_tick(Duration::from_millis(100));
+    /// Set the threshold for approximate discovery
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
 
-    // Process input and collect values
-    let start = Instant::now();
-    let stdin = io::stdin();
-    for line in stdin.lines() {
-        let parsed =
-            json::parse(&line.expect("Error reading input")).expect("Found invalid JSON line");
+    /// Enable approximate discovery
+    pub fn approximate(mut self, approximate: bool) -> Self {
+        self.approximate = approximate;
+        self
+    }
 
-        if args.dynamic {
-            collect_values(&mut values, &mut all_values, "", &parsed);
-        } else {
-            for obj in flatten_json(&parsed) {
-                collect_values(&mut values, &mut all_values, "", &obj);
-            }
-        }
+    /// Use dynamic discovery instead of flattening documents
+    pub fn dynamic(mut self, dynamic: bool) -> Self {
+        self.dynamic = dynamic;
+        self
     }
 
-    // Remove spinner
-    let duration = start.elapsed();
-    spinner.disable_steady_tick();
-    spinner.finish_with_message(format!("Collected values in {:?}", duration));
-
-    // Start new progress for checking combinations
-    spinner = ProgressBar::new(values.len() as u64).with_prefix("Finding dependencies");
-    spinner.set_style(
-        ProgressStyle::with_template("{prefix} [{elapsed_precise}] {bar} {pos:>7}/{len:7}")
-            .unwrap(),
-    );
-
-    // Discover dependencies
-    let inds: Vec<_> = values
-        .keys()
-        .tuple_combinations::<(_, _)>()
-        .par_bridge()
-        .flat_map(|(key1, key2)| {
-            let mut inds = Vec::new();
-            let values1 = values.get(key1).unwrap();
-            let values2 = values.get(key2).unwrap();
-            let intersection = values1.intersection_len(values2);
-
-            if args.approximate {
-                if (intersection as f64) / (values1.len() as f64) >= args.threshold {
-                    inds.push((key1, key2));
-                }
+    /// Only report dependencies between paths in different collections
+    pub fn cross_collection(mut self, cross_collection: bool) -> Self {
+        self.cross_collection = cross_collection;
+        self
+    }
 
-                if (intersection as f64) / (values2.len() as f64) >= args.threshold {
-                    inds.push((key2, key1));
+    /// Show progress on standard error
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Report problems with the input as diagnostics
+    pub fn diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Set which values are considered and how nulls are compared
+    pub fn values(mut self, values: ValueOptions) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// Use the values at a path as rows instead of whole documents
+    pub fn scope(mut self, scope: Option<Scope>) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Discover all inclusion dependencies in a collection of documents
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than [`MAX_VALUES`] distinct values, or if
+    /// static discovery flattens a document into more rows than
+    /// [`ValueOptions::expansion`] allows with
+    /// [`OnExpansion::Fail`](crate::flatten::OnExpansion::Fail). Use
+    /// [`try_discover_collections`](Self::try_discover_collections) to handle
+    /// these as errors instead.
+    pub fn discover<I>(&self, documents: I) -> Vec<InclusionDependency>
+    where
+        I: IntoIterator<Item = json::JsonValue>,
+    {
+        self.discover_collections([(String::new(), documents)])
+    }
+
+    /// Discover inclusion dependencies in several named collections
+    /// where each path is prefixed by the name of its collection
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than [`MAX_VALUES`] distinct values, or if
+    /// static discovery flattens a document into more rows than
+    /// [`ValueOptions::expansion`] allows with
+    /// [`OnExpansion::Fail`](crate::flatten::OnExpansion::Fail). Use
+    /// [`try_discover_collections`](Self::try_discover_collections) to handle
+    /// these as errors instead.
+    pub fn discover_collections<C, I>(&self, collections: C) -> Vec<InclusionDependency>
+    where
+        C: IntoIterator<Item = (String, I)>,
+        I: IntoIterator<Item = json::JsonValue>,
+    {
+        let collections = collections
+            .into_iter()
+            .map(|(name, documents)| (name, documents.into_iter().map(Ok::<_, Error>)));
+        self.try_discover_collections(collections)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Discover inclusion dependencies in collections of documents
+    /// which may fail to load, stopping at the first error
+    pub fn try_discover_collections<C, I, D, E>(
+        &self,
+        collections: C,
+    ) -> Result<Vec<InclusionDependency>, E>
+    where
+        C: IntoIterator<Item = (String, I)>,
+        I: IntoIterator<Item = Result<D, E>>,
+        D: Into<Document>,
+        E: From<Error>,
+    {
+        let mut values: HashMap<Path, RoaringBitmap> = HashMap::new();
+        let mut all_values = Values::new(self.values.clone());
+        let mut collection_names: HashMap<String, usize> = HashMap::new();
+        let mut path_collections: HashMap<Path, usize> = HashMap::new();
+
+        // Initialize spinner
+        let spinner = crate::spinner(self.progress, "Reading input…");
+
+        // Process input and collect values
+        let start = Instant::now();
+        for (name, documents) in collections {
+            // Collections with the same name are treated as one
+            let next_collection = collection_names.len();
+            let collection = *collection_names
+                .entry(name.clone())
+                .or_insert(next_collection);
+
+            // Paths in a named collection start with the collection name
+            let mut root = Path::default();
+            if !name.is_empty() {
+                root = root.key(&name);
+            }
+
+            for parsed in scoped_documents(&self.scope, locate(documents)) {
+                let (origin, parts) = parsed?;
+                let parts: Vec<_> = parts
+                    .iter()
+                    .map(|(prefix, value)| (root.join(prefix), value))
+                    .collect();
+                for (prefix, value) in &parts {
+                    all_values.record_arrays(prefix, value);
                 }
-            } else {
-                if values1.is_subset(values2) {
-                    inds.push((key1, key2));
+                if self.dynamic {
+                    for (prefix, value) in &parts {
+                        collect_values(&mut values, &mut all_values, prefix, value);
+                    }
+                } else {
+                    // Keys of flattened documents are already complete paths
+                    let options = all_values.options();
+                    let flattened = flatten_rows_limited(
+                        parts,
+                        options.arrays,
+                        options.expansion,
+                        &origin,
+                        &self.diagnostics,
+                    )?;
+                    for row in flattened {
+                        for (path, value) in row {
+                            collect_values(&mut values, &mut all_values, &path, &value);
+                        }
+                    }
                 }
-                if values2.is_subset(values1) {
-                    inds.push((key2, key1));
+                check_value_count(all_values.len())?;
+            }
+
+            // Record the collection of all newly observed paths
+            for path in values.keys() {
+                if !path_collections.contains_key(path) {
+                    path_collections.insert(path.clone(), collection);
                 }
             }
+        }
+
+        // Remove spinner
+        let duration = start.elapsed();
+        spinner.disable_steady_tick();
+        spinner.finish_with_message(format!("Collected values in {:?}", duration));
+
+        // Only keep one representation of each array
+        values.retain(|path, _| all_values.keeps_path(path));
+
+        // Skip paths which are unlikely to be meaningful
+        let rejected = screen_paths(
+            &all_values.options().profile,
+            &all_values.profiles,
+            &mut values,
+            |values| values.len(),
+            &self.diagnostics,
+        );
+        let flagged: HashSet<_> = rejected
+            .into_iter()
+            .filter(|(path, _)| values.contains_key(path))
+            .map(|(path, _)| path.to_string())
+            .collect();
+
+        // Start new progress for checking combinations
+        let progress = if self.progress {
+            ProgressBar::new(values.len() as u64)
+        } else {
+            ProgressBar::hidden()
+        };
+        progress.set_prefix("Finding dependencies");
+        progress.set_style(
+            ProgressStyle::with_template("{prefix} [{elapsed_precise}] {bar} {pos:>7}/{len:7}")
+                .unwrap(),
+        );
+
+        // Discover dependencies
+        let mut inds: Vec<_> = values
+            .keys()
+            .tuple_combinations::<(_, _)>()
+            .filter(|(key1, key2)| {
+                !self.cross_collection || path_collections[*key1] != path_collections[*key2]
+            })
+            .par_bridge()
+            .flat_map(|(key1, key2)| {
+                let mut inds = Vec::new();
+                let values1 = values.get(key1).unwrap();
+                let values2 = values.get(key2).unwrap();
+                let intersection = values1.intersection_len(values2);
+                let (len1, len2) = (values1.len(), values2.len());
+
+                if self.approximate {
+                    if (intersection as f64) / (len1 as f64) >= self.threshold {
+                        inds.push(InclusionDependency::new(
+                            key1,
+                            key2,
+                            intersection,
+                            (len1, len2),
+                        ));
+                    }
+
+                    if (intersection as f64) / (len2 as f64) >= self.threshold {
+                        inds.push(InclusionDependency::new(
+                            key2,
+                            key1,
+                            intersection,
+                            (len2, len1),
+                        ));
+                    }
+                } else {
+                    if values1.is_subset(values2) {
+                        inds.push(InclusionDependency::new(
+                            key1,
+                            key2,
+                            intersection,
+                            (len1, len2),
+                        ));
+                    }
+                    if values2.is_subset(values1) {
+                        inds.push(InclusionDependency::new(
+                            key2,
+                            key1,
+                            intersection,
+                            (len2, len1),
+                        ));
+                    }
+                }
+
+                inds
+            })
+            .collect();
 
-            inds
-        })
-        .collect();
+        // Clear final spinner
+        progress.finish_and_clear();
 
-    // Clear final spinner
-    spinner.finish_and_clear();
+        // Sort dependencies for a consistent order
+        // since they are discovered in parallel
+        inds.sort_by(|a, b| (&a.dependent, &a.referenced).cmp(&(&b.dependent, &b.referenced)));
+        for ind in &mut inds {
+            ind.flagged = flagged_paths(&flagged, [&ind.dependent, &ind.referenced]);
+        }
 
-    for ind in inds.iter() {
-        println!("{:?}", ind);
+        Ok(inds)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::values::{Normalization, Normalize, NullSemantics};
 
     use json::{array, object};
 
     #[test]
     fn it_collects_object_values() {
         let obj = object! {a: 3};
-        let mut values: HashMap<String, RoaringBitmap> = HashMap::new();
-        let mut all_values: HashMap<String, usize> = HashMap::new();
+        let mut values: HashMap<Path, RoaringBitmap> = HashMap::new();
+        let mut all_values = Values::default();
 
-        collect_values(&mut values, &mut all_values, "", &obj);
+        collect_values(&mut values, &mut all_values, &Path::default(), &obj);
 
-        assert!(values.get("a").unwrap().contains(0));
-        assert!(all_values.contains_key("3"));
+        assert!(values.get(&Path::parse("a")).unwrap().contains(0));
+        assert!(all_values.indices.contains_key("3"));
     }
 
     #[test]
     fn it_collects_nested_object_values() {
         let obj = object! {a: {b: 3}};
-        let mut values: HashMap<String, RoaringBitmap> = HashMap::new();
-        let mut all_values: HashMap<String, usize> = HashMap::new();
+        let mut values: HashMap<Path, RoaringBitmap> = HashMap::new();
+        let mut all_values = Values::default();
 
-        collect_values(&mut values, &mut all_values, "", &obj);
+        collect_values(&mut values, &mut all_values, &Path::default(), &obj);
 
-        assert!(values.get("a.b").unwrap().contains(0));
-        assert!(all_values.contains_key("3"));
+        assert!(values.get(&Path::parse("a.b")).unwrap().contains(0));
+        assert!(all_values.indices.contains_key("3"));
     }
 
     #[test]
     fn it_collects_array_values() {
         let obj = array![3, 4];
-        let mut values: HashMap<String, RoaringBitmap> = HashMap::new();
-        let mut all_values: HashMap<String, usize> = HashMap::new();
+        let mut values: HashMap<Path, RoaringBitmap> = HashMap::new();
+        let mut all_values = Values::default();
+
+        collect_values(&mut values, &mut all_values, &Path::default(), &obj);
+
+        assert!(values.get(&Path::parse("[*]")).unwrap().contains(0));
+        assert!(values.get(&Path::parse("[*]")).unwrap().contains(1));
+        assert!(all_values.indices.contains_key("3"));
+        assert!(all_values.indices.contains_key("4"));
+    }
+
+    #[test]
+    fn it_limits_the_number_of_values() {
+        assert!(check_value_count(MAX_VALUES as usize).is_ok());
+        assert!(matches!(
+            check_value_count(MAX_VALUES as usize + 1),
+            Err(Error::TooManyValues { .. })
+        ));
+    }
+
+    #[test]
+    fn it_discovers_inclusion_dependencies() {
+        let docs = vec![object! {a: 3}, object! {a: 4, b: 3}];
+        let inds = IndDiscovery::new().discover(docs);
+
+        assert_eq!(inds.len(), 1);
+        assert_eq!(inds[0].dependent, "b");
+        assert_eq!(inds[0].referenced, "a");
+        assert_eq!(inds[0].coverage, 1.0);
+        assert_eq!(inds[0].dependent_distinct, 1);
+        assert_eq!(inds[0].referenced_distinct, 2);
+    }
+
+    #[test]
+    fn it_includes_null_and_empty_values_when_configured() {
+        let docs = vec![object! {a: null, b: ""}, object! {c: null, d: "", e: 1}];
+        let inds = IndDiscovery::new().discover(docs.clone());
+        assert!(inds.is_empty());
+
+        let inds = IndDiscovery::new()
+            .values(ValueOptions {
+                nulls: NullSemantics::NullEqualsNull,
+                keep_empty: true,
+                ..ValueOptions::default()
+            })
+            .discover(docs.clone());
+        let pairs: Vec<_> = inds
+            .iter()
+            .map(|ind| (ind.dependent.as_str(), ind.referenced.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("a", "c"), ("b", "d"), ("c", "a"), ("d", "b")]);
+
+        // Distinct nulls are never included in another path
+        let inds = IndDiscovery::new()
+            .values(ValueOptions {
+                nulls: NullSemantics::NullNotEqualsNull,
+                ..ValueOptions::default()
+            })
+            .discover(docs);
+        assert!(inds.is_empty());
+    }
 
-        collect_values(&mut values, &mut all_values, "", &obj);
+    #[test]
+    fn it_normalizes_values_before_comparing() {
+        let docs = vec![object! {a: 1, b: " X "}, object! {c: "1.0", d: "x"}];
+        let inds = IndDiscovery::new().discover(docs.clone());
+        assert!(inds.is_empty());
+
+        let inds = IndDiscovery::new()
+            .values(ValueOptions {
+                normalize: Normalization::from_steps(&[
+                    Normalize::Trim,
+                    Normalize::CaseFold,
+                    Normalize::Coerce,
+                    Normalize::Numbers,
+                ]),
+                ..ValueOptions::default()
+            })
+            .discover(docs);
+        let pairs: Vec<_> = inds
+            .iter()
+            .map(|ind| (ind.dependent.as_str(), ind.referenced.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("a", "c"), ("b", "d"), ("c", "a"), ("d", "b")]);
+    }
 
-        assert!(values.get("[*]").unwrap().contains(0));
-        assert!(values.get("[*]").unwrap().contains(1));
-        assert!(all_values.contains_key("3"));
-        assert!(all_values.contains_key("4"));
+    #[test]
+    fn it_only_considers_values_within_a_scope() {
+        let docs = vec![object! {id: 1, items: [{parent: 1}, {parent: 2}]}];
+        let inds = IndDiscovery::new().discover(docs.clone());
+        assert!(inds
+            .iter()
+            .any(|ind| ind.dependent == "id" && ind.referenced == "items[*].parent"));
+
+        let inds = IndDiscovery::new()
+            .scope(Some(Scope::new("items[*]")))
+            .discover(docs);
+        assert!(inds.is_empty());
+    }
+
+    #[test]
+    fn it_prefixes_paths_with_collections() {
+        let collections = vec![
+            (
+                "customers".to_string(),
+                vec![object! {id: 1}, object! {id: 2}],
+            ),
+            ("orders".to_string(), vec![object! {customer: 2}]),
+        ];
+        let inds = IndDiscovery::new().discover_collections(collections);
+
+        assert_eq!(inds.len(), 1);
+        assert_eq!(inds[0].dependent, "orders.customer");
+        assert_eq!(inds[0].referenced, "customers.id");
+    }
+
+    #[test]
+    fn it_discovers_only_cross_collection_dependencies() {
+        let collections = vec![
+            (
+                "customers".to_string(),
+                vec![object! {id: 1}, object! {id: 2, referrer: 1}],
+            ),
+            ("orders".to_string(), vec![object! {customer: 2}]),
+        ];
+        let inds = IndDiscovery::new()
+            .cross_collection(true)
+            .discover_collections(collections);
+
+        // customers.referrer is only included in the same collection
+        assert_eq!(inds.len(), 1);
+        assert_eq!(inds[0].dependent, "orders.customer");
+        assert_eq!(inds[0].referenced, "customers.id");
     }
 }
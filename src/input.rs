@@ -0,0 +1,1208 @@
+//! Reading JSON documents from files and standard input
+
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::error::{Error, Result};
+
+use std::cell::Cell;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::string::FromUtf8Error;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use json::JsonValue;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+/// How documents are laid out in the input
+pub enum InputFormat {
+    Jsonl,
+    Array,
+    Stream,
+    Csv,
+    Tsv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+/// Whether the first row of a CSV file contains column names
+pub enum Header {
+    Auto,
+    Yes,
+    No,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+/// How input which cannot be parsed is handled
+pub enum OnError {
+    Fail,
+    Skip,
+    Log,
+}
+
+/// Compression formats which are detected on input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Detect compression from the first bytes of a stream
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if bytes.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    /// Detect compression from the extension of a file
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" | "bzip2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+}
+
+/// Wrap a reader to decompress its contents if necessary
+///
+/// Compression is detected by magic bytes at the start of the
+/// stream, falling back to the extension of the file if given.
+pub fn decompress(
+    mut reader: Box<dyn BufRead>,
+    path: Option<&Path>,
+) -> io::Result<Box<dyn BufRead>> {
+    let compression = Compression::from_magic(reader.fill_buf()?)
+        .or_else(|| path.and_then(Compression::from_extension));
+
+    Ok(match compression {
+        None => reader,
+        Some(Compression::Gzip) => {
+            Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+        }
+        Some(Compression::Zstd) => Box::new(BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(reader)?,
+        )),
+        Some(Compression::Bzip2) => {
+            Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader)))
+        }
+    })
+}
+
+/// A location which input documents can be read from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Source {
+    /// The name of the collection stored in this source
+    pub fn collection(&self) -> String {
+        match self {
+            Source::Stdin => "stdin".to_string(),
+            Source::File(path) => {
+                // Ignore any compression extension before taking the stem
+                let path = if Compression::from_extension(path).is_some() {
+                    Path::new(path.file_stem().unwrap_or_default())
+                } else {
+                    path
+                };
+
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    /// Open the source for reading, decompressing if necessary
+    pub fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        match self {
+            Source::Stdin => decompress(Box::new(io::stdin().lock()), None),
+            Source::File(path) => {
+                decompress(Box::new(BufReader::new(File::open(path)?)), Some(path))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Stdin => write!(f, "standard input"),
+            Source::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Expand a list of input arguments into sources
+///
+/// Each argument may be `-` for standard input, a file, a directory
+/// whose files are all read in order, or a glob pattern.
+pub fn expand_inputs(inputs: &[String]) -> Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for input in inputs {
+        sources.extend(expand_input(input)?);
+    }
+
+    Ok(sources)
+}
+
+/// Expand a single input argument into sources
+fn expand_input(input: &str) -> Result<Vec<Source>> {
+    expand_path(input).map_err(|error| Error::Input {
+        source: input.to_string(),
+        error,
+    })
+}
+
+fn expand_path(input: &str) -> io::Result<Vec<Source>> {
+    let path = PathBuf::from(input);
+    if input == "-" {
+        Ok(vec![Source::Stdin])
+    } else if path.is_dir() {
+        // Read all visible files in the directory sorted by name
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&path)? {
+            let entry_path = entry?.path();
+            let hidden = entry_path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if entry_path.is_file() && !hidden {
+                files.push(entry_path);
+            }
+        }
+        files.sort();
+
+        Ok(files.into_iter().map(Source::File).collect())
+    } else if path.exists() || !input.contains(['*', '?', '[']) {
+        // Let errors for missing files be raised when opened
+        Ok(vec![Source::File(path)])
+    } else {
+        let pattern =
+            glob::glob(input).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut sources = Vec::new();
+        for entry in pattern {
+            let entry_path = entry?;
+            if entry_path.is_file() {
+                sources.push(Source::File(entry_path));
+            }
+        }
+
+        if sources.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No matching files"));
+        }
+
+        Ok(sources)
+    }
+}
+
+/// Expand a list of input arguments into named collections of sources
+///
+/// An argument of the form `NAME=INPUT` places all sources from the
+/// input in a single collection with the given name. Otherwise, each
+/// source is a separate collection named by its file stem.
+pub fn expand_collections(inputs: &[String]) -> Result<Vec<(String, Vec<Source>)>> {
+    let mut collections = Vec::new();
+
+    for input in inputs {
+        match input.split_once('=') {
+            Some((name, named_input)) if !PathBuf::from(input).exists() => {
+                collections.push((name.to_string(), expand_input(named_input)?));
+            }
+            _ => {
+                for source in expand_input(input)? {
+                    collections.push((source.collection(), vec![source]));
+                }
+            }
+        }
+    }
+
+    Ok(collections)
+}
+
+/// Options controlling how documents are read from sources
+#[derive(Clone, Debug)]
+pub struct ReadOptions {
+    pub format: InputFormat,
+    pub on_error: OnError,
+    pub csv: CsvOptions,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            format: InputFormat::Jsonl,
+            on_error: OnError::Fail,
+            csv: CsvOptions::default(),
+        }
+    }
+}
+
+/// Options for reading CSV and TSV input
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    pub header: Header,
+    /// Field delimiter, which defaults to a comma for CSV and a tab for TSV
+    pub delimiter: Option<u8>,
+    pub quote: u8,
+    /// Convert values to numbers, booleans, and nulls instead of strings
+    pub typed: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            header: Header::Auto,
+            delimiter: None,
+            quote: b'"',
+            typed: false,
+        }
+    }
+}
+
+/// The reason a document could not be parsed
+#[derive(Debug)]
+enum Invalid {
+    Json(json::Error),
+    Csv(csv::Error),
+    Utf8(FromUtf8Error),
+}
+
+impl Invalid {
+    fn into_error(self, source: String, line: usize) -> Error {
+        match self {
+            Invalid::Json(error) => Error::InvalidJson {
+                source,
+                line,
+                error,
+            },
+            Invalid::Csv(error) => Error::InvalidCsv {
+                source,
+                line,
+                error,
+            },
+            Invalid::Utf8(error) => Error::InvalidUtf8 {
+                source,
+                line,
+                error,
+            },
+        }
+    }
+}
+
+/// A document which was parsed along with the line it started on
+type Parsed = io::Result<(usize, std::result::Result<JsonValue, Invalid>)>;
+
+/// Parse all documents from a reader in the given format
+fn parse_documents(
+    reader: Box<dyn BufRead>,
+    options: &ReadOptions,
+) -> io::Result<Box<dyn Iterator<Item = Parsed>>> {
+    Ok(match options.format {
+        InputFormat::Jsonl => Box::new(reader.split(b'\n').enumerate().map(|(i, line)| {
+            line.map(|line| (i + 1, decode_line(line).and_then(|line| parse_json(&line))))
+        })),
+        InputFormat::Array => Box::new(StreamSplitter::array(reader).map(parse_split)),
+        InputFormat::Stream => Box::new(StreamSplitter::new(reader).map(parse_split)),
+        InputFormat::Csv | InputFormat::Tsv => {
+            let default_delimiter = if options.format == InputFormat::Tsv {
+                b'\t'
+            } else {
+                b','
+            };
+            let delimiter = options.csv.delimiter.unwrap_or(default_delimiter);
+            Box::new(CsvDocuments::new(reader, delimiter, &options.csv)?)
+        }
+    })
+}
+
+/// Decode a line of input without its line ending
+fn decode_line(mut line: Vec<u8>) -> std::result::Result<String, Invalid> {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line).map_err(Invalid::Utf8)
+}
+
+fn parse_json(text: &str) -> std::result::Result<JsonValue, Invalid> {
+    json::parse(text).map_err(Invalid::Json)
+}
+
+/// Parse a document split from a larger input, reporting
+/// errors at their position within the whole input
+fn parse_split(split: io::Result<Split>) -> Parsed {
+    let (line, column, text) = match split? {
+        (line, column, Ok(text)) => (line, column, text),
+        (line, _, Err(invalid)) => return Ok((line, Err(invalid))),
+    };
+
+    Ok(match parse_json(&text) {
+        Err(Invalid::Json(json::Error::UnexpectedCharacter {
+            ch,
+            line: error_line,
+            column: error_column,
+        })) => {
+            // Columns are only offset on the first line of the document
+            let error_line = line + error_line - 1;
+            let error_column = if error_line == line {
+                column + error_column - 1
+            } else {
+                error_column
+            };
+            let error = json::Error::UnexpectedCharacter {
+                ch,
+                line: error_line,
+                column: error_column,
+            };
+
+            (error_line, Err(Invalid::Json(error)))
+        }
+        parsed => (line, parsed),
+    })
+}
+
+/// Converts each row of a CSV file into a flat JSON object
+/// with one key for each column
+struct CsvDocuments {
+    records: csv::StringRecordsIntoIter<Box<dyn BufRead>>,
+    columns: Vec<String>,
+    first: Option<csv::Result<csv::StringRecord>>,
+    typed: bool,
+}
+
+impl CsvDocuments {
+    fn new(reader: Box<dyn BufRead>, delimiter: u8, options: &CsvOptions) -> io::Result<Self> {
+        let mut records = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(delimiter)
+            .quote(options.quote)
+            .from_reader(reader)
+            .into_records();
+
+        // Peek at the first row to determine the column names
+        let (columns, first) = match records.next() {
+            Some(Ok(record)) => {
+                let is_header = match options.header {
+                    Header::Auto => looks_like_header(&record),
+                    Header::Yes => true,
+                    Header::No => false,
+                };
+
+                if is_header {
+                    (record.iter().map(String::from).collect(), None)
+                } else {
+                    let columns = (1..=record.len()).map(|i| format!("column{}", i));
+                    (columns.collect(), Some(Ok(record)))
+                }
+            }
+            Some(Err(error)) if error.is_io_error() => return Err(error.into()),
+            first => (Vec::new(), first),
+        };
+
+        Ok(CsvDocuments {
+            records,
+            columns,
+            first,
+            typed: options.typed,
+        })
+    }
+
+    fn to_json(&self, record: &csv::StringRecord) -> JsonValue {
+        let mut obj = json::object::Object::new();
+        for (column, field) in self.columns.iter().zip(record.iter()) {
+            let value = if self.typed {
+                typed_value(field)
+            } else {
+                field.into()
+            };
+            obj.insert(column, value);
+        }
+
+        JsonValue::Object(obj)
+    }
+}
+
+impl Iterator for CsvDocuments {
+    type Item = Parsed;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.first.take() {
+            Some(record) => record,
+            None => self.records.next()?,
+        };
+
+        Some(match record {
+            Ok(record) => {
+                let line = record.position().map_or(0, |p| p.line() as usize);
+                Ok((line, Ok(self.to_json(&record))))
+            }
+            Err(error) if error.is_io_error() => Err(error.into()),
+            Err(error) => {
+                let line = error.position().map_or(0, |p| p.line() as usize);
+                Ok((line, Err(Invalid::Csv(error))))
+            }
+        })
+    }
+}
+
+/// Guess whether a row contains column names rather than data
+///
+/// A header must have distinct non-empty names, none of which are numbers.
+fn looks_like_header(record: &csv::StringRecord) -> bool {
+    let mut names = HashSet::new();
+    record
+        .iter()
+        .all(|field| !field.is_empty() && field.parse::<f64>().is_err() && names.insert(field))
+}
+
+/// Convert a CSV field to the JSON value it represents
+fn typed_value(field: &str) -> JsonValue {
+    if field.is_empty() {
+        return JsonValue::Null;
+    }
+
+    if let Ok(n) = field.parse::<i64>() {
+        n.into()
+    } else if let Some(n) = field.parse::<f64>().ok().filter(|n| n.is_finite()) {
+        n.into()
+    } else {
+        match field {
+            "true" => true.into(),
+            "false" => false.into(),
+            _ => field.into(),
+        }
+    }
+}
+
+/// Where a splitter is within the top-level array of its input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArrayState {
+    /// Before the opening bracket
+    Start,
+    /// After the opening bracket, expecting an element or the end
+    First,
+    /// After a comma, expecting an element
+    Element,
+    /// After an element, expecting a comma or the end
+    Next,
+    /// After the closing bracket
+    End,
+    /// After an error, ignoring the rest of the input
+    Failed,
+}
+
+/// The text of a single document along with the line and column it
+/// started on, or the reason the input could not be split
+type Split = (usize, usize, std::result::Result<String, Invalid>);
+
+/// Splits a stream of concatenated JSON documents separated by
+/// optional whitespace into the text of each individual document
+///
+/// The elements of a JSON array can also be split in the same way so
+/// that the whole array never needs to be held in memory.
+struct StreamSplitter {
+    reader: Box<dyn BufRead>,
+    lineno: usize,
+    column: usize,
+    pending: VecDeque<Split>,
+    document: String,
+    start_line: usize,
+    start_column: usize,
+    depth: usize,
+    in_string: bool,
+    escape: bool,
+    scalar: bool,
+    array: Option<ArrayState>,
+}
+
+impl StreamSplitter {
+    fn new(reader: Box<dyn BufRead>) -> Self {
+        StreamSplitter {
+            reader,
+            lineno: 0,
+            column: 0,
+            pending: VecDeque::new(),
+            document: String::new(),
+            start_line: 0,
+            start_column: 0,
+            depth: 0,
+            in_string: false,
+            escape: false,
+            scalar: false,
+            array: None,
+        }
+    }
+
+    /// Split the elements of an array instead of concatenated documents
+    fn array(reader: Box<dyn BufRead>) -> Self {
+        StreamSplitter {
+            array: Some(ArrayState::Start),
+            ..StreamSplitter::new(reader)
+        }
+    }
+
+    /// Mark the current document as complete
+    fn finish_document(&mut self) {
+        let document = std::mem::take(&mut self.document);
+        self.pending
+            .push_back((self.start_line, self.start_column, Ok(document)));
+        self.scalar = false;
+
+        if self.array.is_some() {
+            self.array = Some(ArrayState::Next);
+        }
+    }
+
+    /// Stop splitting the array since the input is not valid
+    fn fail(&mut self, error: json::Error) {
+        self.pending
+            .push_back((self.lineno.max(1), self.column, Err(Invalid::Json(error))));
+        self.array = Some(ArrayState::Failed);
+    }
+
+    /// Discard the current document since one of its lines is not valid UTF-8
+    fn reject_line(&mut self, error: FromUtf8Error) {
+        if self.array == Some(ArrayState::Failed) {
+            return;
+        }
+
+        self.document.clear();
+        self.depth = 0;
+        self.in_string = false;
+        self.escape = false;
+        self.scalar = false;
+        self.pending
+            .push_back((self.lineno, 1, Err(Invalid::Utf8(error))));
+
+        // The elements after the line cannot be found reliably
+        if self.array.is_some() {
+            self.array = Some(ArrayState::Failed);
+        }
+    }
+
+    /// Handle a character between the elements of an array,
+    /// returning whether it is the start of an element
+    fn delimit(&mut self, c: char) -> bool {
+        let state = match self.array {
+            Some(state) => state,
+            None => return true,
+        };
+
+        let next = match (state, c) {
+            (ArrayState::Failed, _) => return false,
+            (ArrayState::Start, '[') => ArrayState::First,
+            (ArrayState::Start, _) => {
+                self.fail(json::Error::WrongType("array".to_string()));
+                return false;
+            }
+            (ArrayState::First | ArrayState::Next, ']') => ArrayState::End,
+            (ArrayState::Next, ',') => ArrayState::Element,
+            (ArrayState::First | ArrayState::Element, c) if !matches!(c, ',' | ']') => return true,
+            _ => {
+                self.fail(json::Error::UnexpectedCharacter {
+                    ch: c,
+                    line: self.lineno,
+                    column: self.column,
+                });
+                return false;
+            }
+        };
+        self.array = Some(next);
+
+        false
+    }
+
+    fn push(&mut self, c: char) {
+        self.column += 1;
+
+        // Bare scalars end at whitespace or the start of another value
+        let delimiter = self.array.is_some() && matches!(c, ',' | ']');
+        if self.scalar && (c.is_whitespace() || matches!(c, '{' | '[' | '"') || delimiter) {
+            self.finish_document();
+        }
+
+        if self.document.is_empty() {
+            if c.is_whitespace() || !self.delimit(c) {
+                return;
+            }
+            self.start_line = self.lineno;
+            self.start_column = self.column;
+        }
+        self.document.push(c);
+
+        if self.in_string {
+            if self.escape {
+                self.escape = false;
+            } else if c == '\\' {
+                self.escape = true;
+            } else if c == '"' {
+                self.in_string = false;
+                if self.depth == 0 {
+                    self.finish_document();
+                }
+            }
+        } else {
+            match c {
+                '"' => self.in_string = true,
+                '{' | '[' => self.depth += 1,
+                '}' | ']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        self.finish_document();
+                    }
+                }
+                _ => {
+                    if self.depth == 0 {
+                        self.scalar = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handle the end of the input
+    fn finish(&mut self) {
+        // An incomplete document will fail to parse on its own
+        let truncated = self.depth > 0 || self.in_string;
+        if !self.document.is_empty() {
+            self.finish_document();
+        }
+
+        match self.array {
+            Some(ArrayState::End | ArrayState::Failed) | None => {}
+            Some(_) if truncated => self.array = Some(ArrayState::Failed),
+            Some(_) => self.fail(json::Error::UnexpectedEndOfJson),
+        }
+    }
+}
+
+impl Iterator for StreamSplitter {
+    type Item = io::Result<Split>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::new();
+        while self.pending.is_empty() {
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    // Anything left over at the end is the final document
+                    self.finish();
+                    if self.pending.is_empty() {
+                        return None;
+                    }
+                }
+                Ok(_) => {
+                    self.lineno += 1;
+                    self.column = 0;
+                    match String::from_utf8(std::mem::take(&mut line)) {
+                        Ok(text) => text.chars().for_each(|c| self.push(c)),
+                        Err(error) => self.reject_line(error),
+                    }
+                }
+                Err(error) => return Some(Err(error)),
+            }
+        }
+
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Identifies a document in messages about it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// The position of the document in the input starting from 1
+    Index(usize),
+    /// The line the document starts on within the source it was read from
+    Line { source: Arc<str>, line: usize },
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Index(index) => write!(f, "document {}", index),
+            Origin::Line { source, line } => {
+                write!(f, "document on line {} of {}", line, source)
+            }
+        }
+    }
+}
+
+/// A document along with where it was read from, if known
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    pub value: JsonValue,
+    pub origin: Option<Origin>,
+}
+
+impl From<JsonValue> for Document {
+    fn from(value: JsonValue) -> Self {
+        Document {
+            value,
+            origin: None,
+        }
+    }
+}
+
+/// A document paired with where it came from
+pub(crate) type Located = (Origin, JsonValue);
+
+/// Identify documents without a known origin by their position
+pub(crate) fn locate<I, D, E>(documents: I) -> impl Iterator<Item = std::result::Result<Located, E>>
+where
+    I: IntoIterator<Item = std::result::Result<D, E>>,
+    D: Into<Document>,
+{
+    documents.into_iter().enumerate().map(|(i, document)| {
+        document.map(|document| {
+            let Document { value, origin } = document.into();
+            (origin.unwrap_or(Origin::Index(i + 1)), value)
+        })
+    })
+}
+
+/// Reads JSON documents from a list of sources
+///
+/// Documents which are not valid JSON are handled according to the
+/// error policy and the number of skipped documents is counted.
+pub struct Reader<'a> {
+    sources: std::vec::IntoIter<Source>,
+    current: Option<(Arc<str>, Box<dyn Iterator<Item = Parsed>>)>,
+    options: ReadOptions,
+    skipped: &'a Cell<usize>,
+    diagnostics: Diagnostics,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(sources: Vec<Source>, options: ReadOptions, skipped: &'a Cell<usize>) -> Self {
+        Reader {
+            sources: sources.into_iter(),
+            current: None,
+            options,
+            skipped,
+            diagnostics: Diagnostics::default(),
+        }
+    }
+
+    /// Report documents logged by [`OnError::Log`] as diagnostics
+    pub fn diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+}
+
+impl Iterator for Reader<'_> {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Open the next source when the current one is exhausted
+            if self.current.is_none() {
+                let source = self.sources.next()?;
+                match source
+                    .open()
+                    .and_then(|reader| parse_documents(reader, &self.options))
+                {
+                    Ok(documents) => self.current = Some((source.to_string().into(), documents)),
+                    Err(error) => {
+                        return Some(Err(Error::Input {
+                            source: source.to_string(),
+                            error,
+                        }))
+                    }
+                }
+            }
+
+            let (source, documents) = self.current.as_mut().unwrap();
+            let (line, parsed) = match documents.next() {
+                Some(Ok(document)) => document,
+                Some(Err(error)) => {
+                    return Some(Err(Error::Input {
+                        source: source.to_string(),
+                        error,
+                    }))
+                }
+                None => {
+                    self.current = None;
+                    continue;
+                }
+            };
+
+            match parsed {
+                Ok(value) => {
+                    let source = source.clone();
+                    let origin = Origin::Line { source, line };
+                    return Some(Ok(Document {
+                        value,
+                        origin: Some(origin),
+                    }));
+                }
+                Err(error) => {
+                    let error = error.into_error(source.to_string(), line);
+                    match self.options.on_error {
+                        OnError::Fail => return Some(Err(error)),
+                        OnError::Skip => self.skipped.set(self.skipped.get() + 1),
+                        OnError::Log => {
+                            let message = format!("Warning: {}", error);
+                            self.diagnostics.report(Diagnostic::Warning(message));
+                            self.skipped.set(self.skipped.get() + 1);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::io::Write;
+
+    use json::object;
+
+    #[test]
+    fn it_names_collections_by_file_stem() {
+        let source = Source::File(PathBuf::from("dump/orders.jsonl"));
+
+        assert_eq!(source.collection(), "orders");
+        assert_eq!(Source::Stdin.collection(), "stdin");
+
+        let compressed = Source::File(PathBuf::from("dump/orders.jsonl.gz"));
+        assert_eq!(compressed.collection(), "orders");
+    }
+
+    fn read_compressed(compressed: Vec<u8>) -> String {
+        let reader = decompress(Box::new(io::Cursor::new(compressed)), None).unwrap();
+
+        reader.lines().map(|line| line.unwrap()).collect()
+    }
+
+    #[test]
+    fn it_reads_gzip_input() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(b"{\"a\": 1}\n").unwrap();
+
+        assert_eq!(read_compressed(encoder.finish().unwrap()), "{\"a\": 1}");
+    }
+
+    #[test]
+    fn it_reads_zstd_input() {
+        let compressed = zstd::encode_all(&b"{\"a\": 1}\n"[..], 0).unwrap();
+
+        assert_eq!(read_compressed(compressed), "{\"a\": 1}");
+    }
+
+    #[test]
+    fn it_reads_bzip2_input() {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(b"{\"a\": 1}\n").unwrap();
+
+        assert_eq!(read_compressed(encoder.finish().unwrap()), "{\"a\": 1}");
+    }
+
+    #[test]
+    fn it_reads_uncompressed_input() {
+        assert_eq!(read_compressed(b"{}\n".to_vec()), "{}");
+    }
+
+    #[test]
+    fn it_expands_directories() {
+        let dir = env::temp_dir().join(format!("jd-expand-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b.jsonl"), "").unwrap();
+        fs::write(dir.join("a.jsonl"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+
+        let sources = expand_inputs(&[dir.to_string_lossy().into_owned()]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            sources,
+            vec![
+                Source::File(dir.join("a.jsonl")),
+                Source::File(dir.join("b.jsonl"))
+            ]
+        );
+    }
+
+    #[test]
+    fn it_expands_named_collections() {
+        let inputs = ["orders=dump/orders.jsonl".to_string(), "-".to_string()];
+        let collections = expand_collections(&inputs).unwrap();
+
+        assert_eq!(
+            collections,
+            vec![
+                (
+                    "orders".to_string(),
+                    vec![Source::File(PathBuf::from("dump/orders.jsonl"))]
+                ),
+                ("stdin".to_string(), vec![Source::Stdin])
+            ]
+        );
+    }
+
+    #[test]
+    fn it_fails_on_unmatched_globs() {
+        let result = expand_inputs(&["no-such-dir/*.jsonl".to_string()]);
+
+        assert!(matches!(result, Err(Error::Input { .. })));
+    }
+
+    fn write_input(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("jd-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn it_fails_on_invalid_lines() {
+        let path = write_input("fail", "{}\n{\n");
+        let skipped = Cell::new(0);
+        let reader = Reader::new(
+            vec![Source::File(path.clone())],
+            ReadOptions::default(),
+            &skipped,
+        );
+        let result = reader.collect::<Result<Vec<_>>>();
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(Error::InvalidJson { line: 2, .. })));
+    }
+
+    #[test]
+    fn it_skips_invalid_lines() {
+        let path = write_input("skip", "{\n{}\nnope\n{}\n");
+        let skipped = Cell::new(0);
+        let reader = Reader::new(
+            vec![Source::File(path.clone())],
+            ReadOptions {
+                on_error: OnError::Skip,
+                ..ReadOptions::default()
+            },
+            &skipped,
+        );
+        let documents = reader.collect::<Result<Vec<_>>>();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(documents.unwrap().len(), 2);
+        assert_eq!(skipped.get(), 2);
+    }
+
+    #[test]
+    fn it_reports_invalid_utf8_as_an_invalid_document() {
+        let read = |format, contents: &[u8]| {
+            let reader = Box::new(io::Cursor::new(contents.to_vec()));
+            let options = ReadOptions {
+                format,
+                ..ReadOptions::default()
+            };
+            let (mut documents, mut invalid) = (Vec::new(), Vec::new());
+            for parsed in parse_documents(reader, &options).unwrap() {
+                match parsed.unwrap() {
+                    (_, Ok(document)) => documents.push(document),
+                    (line, Err(Invalid::Utf8(_))) => invalid.push(line),
+                    (_, Err(error)) => panic!("{:?}", error),
+                }
+            }
+
+            (documents, invalid)
+        };
+
+        let contents = b"{\"a\": 1}\n{\"a\": \"\xff\"}\r\n{\"a\": 2}\n";
+        for format in [InputFormat::Jsonl, InputFormat::Stream] {
+            let (documents, invalid) = read(format, contents);
+            assert_eq!(documents, vec![object! {a: 1}, object! {a: 2}]);
+            assert_eq!(invalid, vec![2]);
+        }
+
+        let (documents, invalid) = read(InputFormat::Array, b"[1,\n\"\xff\",\n2]");
+        assert_eq!(documents, vec![JsonValue::from(1)]);
+        assert_eq!(invalid, vec![2]);
+    }
+
+    fn read_format(format: InputFormat, contents: &str) -> Vec<JsonValue> {
+        read_with_options(
+            ReadOptions {
+                format,
+                ..ReadOptions::default()
+            },
+            contents,
+        )
+    }
+
+    fn read_with_options(options: ReadOptions, contents: &str) -> Vec<JsonValue> {
+        let reader = Box::new(io::Cursor::new(contents.as_bytes().to_vec()));
+
+        parse_documents(reader, &options)
+            .unwrap()
+            .map(|parsed| parsed.unwrap().1.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_reads_array_elements() {
+        let documents = read_format(InputFormat::Array, "[{\"a\": 1},\n {\"a\": 2}]");
+
+        assert_eq!(documents, vec![object! {a: 1}, object! {a: 2}]);
+    }
+
+    fn read_errors(format: InputFormat, contents: &str) -> Vec<(usize, String)> {
+        let reader = Box::new(io::Cursor::new(contents.as_bytes().to_vec()));
+        let options = ReadOptions {
+            format,
+            ..ReadOptions::default()
+        };
+
+        parse_documents(reader, &options)
+            .unwrap()
+            .filter_map(|parsed| match parsed.unwrap() {
+                (line, Err(Invalid::Json(error))) => Some((line, error.to_string())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_reads_nested_and_scalar_array_elements() {
+        let documents = read_format(InputFormat::Array, " [[1, [2]], \"a,]\", 3,{}]\n");
+
+        assert_eq!(
+            documents,
+            vec![json::array![1, [2]], "a,]".into(), 3.into(), object! {}]
+        );
+        assert!(read_format(InputFormat::Array, "[ ]").is_empty());
+    }
+
+    #[test]
+    fn it_reports_the_position_of_invalid_array_elements() {
+        assert_eq!(
+            read_errors(InputFormat::Array, "[\n  {\"a\": 1},\n  {\"a\" 2}\n]"),
+            vec![(3, "Unexpected character: 2 at (3:8)".to_string())]
+        );
+        assert_eq!(
+            read_errors(InputFormat::Array, "[1,\n 2 3]"),
+            vec![(2, "Unexpected character: 3 at (2:4)".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_fails_on_input_which_is_not_an_array() {
+        assert_eq!(
+            read_errors(InputFormat::Array, "{\"a\": 1}"),
+            vec![(1, "Wrong type, expected: array".to_string())]
+        );
+        assert_eq!(
+            read_errors(InputFormat::Array, "[1, 2"),
+            vec![(1, "Unexpected end of JSON".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_reads_concatenated_documents() {
+        let contents = "{\n  \"a\": \"}\\\"\"\n}{\"a\": [2]}\n\n3 \"x\"";
+        let documents = read_format(InputFormat::Stream, contents);
+
+        assert_eq!(
+            documents,
+            vec![object! {a: "}\""}, object! {a: [2]}, 3.into(), "x".into()]
+        );
+    }
+
+    #[test]
+    fn it_reports_the_starting_line_of_streamed_documents() {
+        let reader = Box::new(io::Cursor::new(b"{}\n{\n\"a\":\n}".to_vec()));
+        let lines: Vec<_> = StreamSplitter::new(reader)
+            .map(|document| document.unwrap().0)
+            .collect();
+
+        assert_eq!(lines, vec![1, 2]);
+    }
+
+    #[test]
+    fn it_reads_csv_rows_as_objects() {
+        let documents = read_format(InputFormat::Csv, "id,name\n1,\"Smith, J\"\n2,\n");
+
+        assert_eq!(
+            documents,
+            vec![
+                object! {id: "1", name: "Smith, J"},
+                object! {id: "2", name: ""}
+            ]
+        );
+    }
+
+    #[test]
+    fn it_names_csv_columns_without_a_header() {
+        let documents = read_format(InputFormat::Tsv, "1\ta\n2\tb\n");
+
+        assert_eq!(
+            documents,
+            vec![
+                object! {column1: "1", column2: "a"},
+                object! {column1: "2", column2: "b"}
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reads_typed_csv_values() {
+        let options = ReadOptions {
+            format: InputFormat::Csv,
+            csv: CsvOptions {
+                header: Header::No,
+                delimiter: Some(b';'),
+                quote: b'\'',
+                typed: true,
+            },
+            ..ReadOptions::default()
+        };
+        let documents = read_with_options(options, "id;1;1.5;true;;'a;b'\n");
+
+        assert_eq!(
+            documents,
+            vec![object! {
+                column1: "id",
+                column2: 1,
+                column3: 1.5,
+                column4: true,
+                column5: null,
+                column6: "a;b"
+            }]
+        );
+    }
+
+    #[test]
+    fn it_fails_on_ragged_csv_rows() {
+        let path = write_input("ragged.csv", "a,b\n1,2\n3\n");
+        let skipped = Cell::new(0);
+        let reader = Reader::new(
+            vec![Source::File(path.clone())],
+            ReadOptions {
+                format: InputFormat::Csv,
+                ..ReadOptions::default()
+            },
+            &skipped,
+        );
+        let result: Result<Vec<_>> = reader.collect();
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(result, Err(Error::InvalidCsv { line: 3, .. })));
+    }
+}
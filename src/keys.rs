@@ -0,0 +1,350 @@
+//! Discovery of minimal keys (unique path combinations)
+
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::error::Error;
+use crate::fd::{check_violations, collect_partitions, generate_next_level, Collected, Element};
+use crate::input::{locate, Document};
+use crate::output::{Dependency, Sink};
+use crate::path::{self, PathStyle};
+use crate::profile::flagged_paths;
+use crate::scope::{scoped_documents, Scope};
+use crate::values::ValueOptions;
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use json::object;
+use rayon::prelude::*;
+use roaring::bitmap::RoaringBitmap;
+
+/// A set of paths whose values identify each document
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub paths: Vec<String>,
+    /// Fraction of documents which must be removed
+    /// for the key to hold exactly (approximate only)
+    pub error: Option<f64>,
+    /// Number of documents identified by the key (approximate only)
+    pub support: Option<u64>,
+    /// Paths in the key which failed the profiling thresholds
+    pub flagged: Vec<String>,
+}
+
+impl Dependency for Key {
+    fn to_json(&self) -> json::JsonValue {
+        let mut obj = object! {
+            paths: self.paths.clone(),
+        };
+        if let Some(error) = self.error {
+            obj["error"] = error.into();
+        }
+        if let Some(support) = self.support {
+            obj["support"] = support.into();
+        }
+        if !self.flagged.is_empty() {
+            obj["flagged"] = self.flagged.clone().into();
+        }
+
+        obj
+    }
+
+    fn to_text(&self) -> String {
+        match self.error {
+            Some(error) => format!("{:?} (error {:.3})", self.paths, error),
+            None => format!("{:?}", self.paths),
+        }
+    }
+
+    fn with_path_style(self, style: PathStyle) -> Self {
+        Key {
+            paths: self.paths.iter().map(|p| path::format(p, style)).collect(),
+            flagged: self
+                .flagged
+                .iter()
+                .map(|p| path::format(p, style))
+                .collect(),
+            ..self
+        }
+    }
+}
+
+/// Builder for minimal key discovery
+///
+/// A set of paths is a key when every document has a value for each
+/// path and no two documents share values for all of the paths. For
+/// paths with several values in a document (such as arrays), documents
+/// are only distinguished when they do not share any of those values.
+#[derive(Clone, Debug)]
+pub struct KeyDiscovery {
+    threshold: f64,
+    approximate: bool,
+    dynamic: bool,
+    progress: bool,
+    diagnostics: Diagnostics,
+    max_size: Option<usize>,
+    values: ValueOptions,
+    scope: Option<Scope>,
+}
+
+impl Default for KeyDiscovery {
+    fn default() -> Self {
+        KeyDiscovery {
+            threshold: 0.9,
+            approximate: false,
+            dynamic: true,
+            progress: false,
+            diagnostics: Diagnostics::default(),
+            max_size: None,
+            values: ValueOptions::default(),
+            scope: None,
+        }
+    }
+}
+
+impl KeyDiscovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the threshold for approximate discovery
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Enable approximate discovery
+    pub fn approximate(mut self, approximate: bool) -> Self {
+        self.approximate = approximate;
+        self
+    }
+
+    /// Use dynamic discovery instead of flattening documents
+    pub fn dynamic(mut self, dynamic: bool) -> Self {
+        self.dynamic = dynamic;
+        self
+    }
+
+    /// Show progress on standard error
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Report progress and problems with the input as diagnostics
+    pub fn diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Set which values are considered and how nulls are compared
+    pub fn values(mut self, values: ValueOptions) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// Use the values at a path as rows instead of whole documents
+    pub fn scope(mut self, scope: Option<Scope>) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Only find keys with at most this many paths
+    pub fn max_size(mut self, max_size: Option<usize>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Discover all minimal keys in a collection of documents
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than [`MAX_DOCUMENTS`](crate::fd::MAX_DOCUMENTS)
+    /// documents or rows in a scope, or if static discovery flattens a document
+    /// into more rows than [`ValueOptions::expansion`] allows with
+    /// [`OnExpansion::Fail`](crate::flatten::OnExpansion::Fail). Use
+    /// [`try_discover_into`](Self::try_discover_into) to handle these as
+    /// errors instead.
+    pub fn discover<I>(&self, documents: I) -> Vec<Key>
+    where
+        I: IntoIterator<Item = json::JsonValue>,
+    {
+        let mut keys = Vec::new();
+        self.try_discover_into(documents.into_iter().map(Ok::<_, Error>), &mut keys)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        keys
+    }
+
+    /// Discover minimal keys from documents which may fail to load and
+    /// emit them to a sink as each size of key is completed
+    pub fn try_discover_into<I, D, S, E>(&self, documents: I, sink: &mut S) -> Result<(), E>
+    where
+        I: IntoIterator<Item = Result<D, E>>,
+        D: Into<Document>,
+        S: Sink<Key>,
+        E: From<Error>,
+    {
+        let Collected {
+            paths,
+            mut partitions,
+            presence,
+            flagged,
+            row_count,
+            ..
+        } = match collect_partitions(
+            scoped_documents(&self.scope, locate(documents)),
+            self.dynamic,
+            self.values.clone(),
+            self.progress,
+            &self.diagnostics,
+        )? {
+            Some(collected) => collected,
+            None => return Ok(()),
+        };
+
+        // Start with each path as a candidate key
+        let mut level: HashMap<_, _> = paths
+            .keys()
+            .map(|a| {
+                let element = Element {
+                    bitmap: RoaringBitmap::new(),
+                    valid: true,
+                };
+                (RoaringBitmap::from(*a), element)
+            })
+            .collect();
+
+        for size in 1..=paths.len() {
+            self.diagnostics.report(Diagnostic::Progress(format!(
+                "Checking keys of size {}...",
+                size
+            )));
+
+            let mut keys: Vec<_> = level
+                .par_iter()
+                .filter_map(|(x, _)| {
+                    // Documents missing any of the paths cannot be identified
+                    let present = x
+                        .iter()
+                        .map(|a| presence.get(&a).unwrap().clone())
+                        .reduce(|all, rows| all & rows)
+                        .unwrap();
+                    let missing = row_count as u64 - present.len();
+
+                    let violations = partitions.get(x).unwrap().key_error() + missing;
+                    check_violations(
+                        violations,
+                        row_count as u32,
+                        self.approximate,
+                        self.threshold,
+                    )
+                    .then(|| (x.clone(), violations))
+                })
+                .collect();
+
+            // Keys are not extended further since any superset is not minimal
+            for (x, _) in &keys {
+                level.remove(x);
+            }
+
+            let mut found: Vec<_> = keys
+                .drain(..)
+                .map(|(x, violations)| Key {
+                    paths: x
+                        .iter()
+                        .map(|a| paths.get(&a).unwrap().to_string())
+                        .sorted()
+                        .collect(),
+                    flagged: Vec::new(),
+                    error: self
+                        .approximate
+                        .then(|| violations as f64 / row_count as f64),
+                    support: self
+                        .approximate
+                        .then(|| (row_count as u64).saturating_sub(violations)),
+                })
+                .collect();
+            found.sort_by(|a, b| a.paths.cmp(&b.paths));
+            for mut key in found {
+                key.flagged = flagged_paths(&flagged, &key.paths);
+                sink.emit(key);
+            }
+
+            if level.is_empty() || self.max_size.is_some_and(|max_size| size >= max_size) {
+                break;
+            }
+
+            // Candidates in the next level only contain non-keys
+            partitions.retain(|x, _| x.len() <= 1 || x.len() >= size as u64);
+            level = generate_next_level(&level, &mut partitions);
+            if level.is_empty() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use json::object;
+
+    fn key_paths(keys: &[Key]) -> Vec<Vec<&str>> {
+        keys.iter()
+            .map(|key| key.paths.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn it_discovers_minimal_keys() {
+        let docs = vec![
+            object! {id: 1, a: 1, b: 1, c: 1},
+            object! {id: 2, a: 1, b: 2, c: 1},
+            object! {id: 3, a: 2, b: 1, c: 2},
+            object! {id: 4, a: 2, b: 2, c: 1},
+        ];
+        let keys = KeyDiscovery::new().discover(docs);
+
+        assert_eq!(key_paths(&keys), vec![vec!["id"], vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn it_requires_keys_in_every_document() {
+        let docs = vec![
+            object! {id: 1, name: "a"},
+            object! {id: 2, name: "b"},
+            object! {name: "c"},
+        ];
+        let keys = KeyDiscovery::new().discover(docs);
+
+        assert_eq!(key_paths(&keys), vec![vec!["name"]]);
+    }
+
+    #[test]
+    fn it_discovers_keys_in_arrays() {
+        let docs = vec![
+            object! {tags: [1, 2], group: 1},
+            object! {tags: [3], group: 1},
+            object! {tags: [2, 4], group: 2},
+        ];
+        let keys = KeyDiscovery::new().discover(docs);
+
+        assert_eq!(key_paths(&keys), vec![vec!["group", "tags[*]"]]);
+    }
+
+    #[test]
+    fn it_discovers_approximate_keys() {
+        let docs = (0..10).map(|i| object! {id: i.min(8)});
+        let keys = KeyDiscovery::new()
+            .approximate(true)
+            .threshold(0.9)
+            .discover(docs);
+
+        assert_eq!(key_paths(&keys), vec![vec!["id"]]);
+        assert_eq!(keys[0].error, Some(0.1));
+        assert_eq!(keys[0].support, Some(9));
+    }
+}
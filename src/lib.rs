@@ -0,0 +1,44 @@
+//! Fast dependency discovery on nested JSON data
+//!
+//! Functional dependencies are discovered with [`FdDiscovery`],
+//! inclusion dependencies with [`IndDiscovery`], and minimal keys
+//! with [`KeyDiscovery`]. All accept any iterator of parsed JSON documents.
+
+pub mod diagnostics;
+pub mod error;
+pub mod fd;
+pub mod filter;
+pub mod flatten;
+pub mod ind;
+pub mod input;
+pub mod keys;
+pub mod output;
+mod partition;
+pub mod path;
+pub mod profile;
+pub mod scope;
+pub mod values;
+
+use std::time::Duration;
+
+use indicatif::ProgressBar;
+
+pub use diagnostics::{Diagnostic, Diagnostics};
+pub use error::Error;
+pub use fd::{Completion, FdDiscovery, FunctionalDependency};
+pub use ind::{InclusionDependency, IndDiscovery};
+pub use keys::{Key, KeyDiscovery};
+pub use values::{Normalization, Normalize, NullSemantics, ValueOptions};
+
+/// Create a spinner which is only drawn when progress is enabled
+pub(crate) fn spinner(progress: bool, message: &'static str) -> ProgressBar {
+    let spinner = if progress {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::hidden()
+    };
+    let spinner = spinner.with_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+
+    spinner
+}
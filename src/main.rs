@@ -1,9 +1,6 @@
-#![feature(map_first_last)]
-mod fd;
-mod flatten;
-mod ind;
+mod cli;
 
-use std::io;
+use std::process;
 
 use clap::{Parser, Subcommand};
 
@@ -17,26 +14,24 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    Fd(fd::FDArgs),
-    Ind(ind::INDArgs),
-    Flatten,
+    Fd(cli::FDArgs),
+    Ind(cli::INDArgs),
+    Keys(cli::KeysArgs),
+    Flatten(cli::FlattenArgs),
 }
 
 fn main() {
     let args = Cli::parse();
 
-    match args.command {
-        Commands::Fd(fd_args) => fd::discover(fd_args),
-        Commands::Ind(ind_args) => ind::discover(ind_args),
-        Commands::Flatten => {
-            let stdin = io::stdin();
-            for line in stdin.lines() {
-                let parsed = json::parse(&line.expect("Error reading input"))
-                    .expect("Found invalid JSON line");
-                for obj in flatten::flatten_json(&parsed) {
-                    println!("{}", obj.dump());
-                }
-            }
-        }
+    let result = match args.command {
+        Commands::Fd(fd_args) => cli::fd(fd_args),
+        Commands::Ind(ind_args) => cli::ind(ind_args),
+        Commands::Keys(keys_args) => cli::keys(keys_args),
+        Commands::Flatten(flatten_args) => cli::flatten(flatten_args),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(e.exit_code());
     }
 }
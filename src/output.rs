@@ -0,0 +1,111 @@
+//! Output of discovered dependencies in different formats
+
+use clap::ValueEnum;
+use json::JsonValue;
+
+use crate::path::PathStyle;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+/// Format used when writing discovered dependencies
+pub enum OutputFormat {
+    Text,
+    Json,
+    Jsonl,
+}
+
+/// A dependency which can be written in any output format
+pub trait Dependency {
+    /// Convert the dependency to a JSON object
+    fn to_json(&self) -> JsonValue;
+
+    /// Convert the dependency to a single line of text
+    fn to_text(&self) -> String;
+
+    /// Rewrite all paths in the dependency using a particular syntax
+    fn with_path_style(self, style: PathStyle) -> Self
+    where
+        Self: Sized;
+}
+
+/// A destination for dependencies as they are discovered
+pub trait Sink<D> {
+    fn emit(&mut self, dependency: D);
+}
+
+impl<D> Sink<D> for Vec<D> {
+    fn emit(&mut self, dependency: D) {
+        self.push(dependency);
+    }
+}
+
+/// Writes dependencies to standard output as they are emitted
+pub struct Printer {
+    format: OutputFormat,
+    path_style: PathStyle,
+    count: usize,
+}
+
+impl Printer {
+    pub fn new(format: OutputFormat) -> Self {
+        Printer {
+            format,
+            path_style: PathStyle::default(),
+            count: 0,
+        }
+    }
+
+    /// Set the syntax used to write paths
+    pub fn path_style(mut self, path_style: PathStyle) -> Self {
+        self.path_style = path_style;
+        self
+    }
+
+    /// Record that discovery stopped early as a final object in JSON
+    /// output, since text output only reports this on standard error
+    pub fn mark_partial(&mut self, reason: &str) {
+        if self.format != OutputFormat::Text {
+            self.write_json(json::object! {partial: true, reason: reason});
+        }
+    }
+
+    /// Write a single object as an element of JSON output
+    fn write_json(&mut self, value: JsonValue) {
+        if self.format == OutputFormat::Jsonl {
+            println!("{}", value.dump());
+        } else {
+            // Open the array before the first element and
+            // separate all following elements with a comma
+            if self.count == 0 {
+                print!("[\n  ");
+            } else {
+                print!(",\n  ");
+            }
+            print!("{}", value.dump());
+        }
+
+        self.count += 1;
+    }
+
+    /// Complete the output after all dependencies have been emitted
+    pub fn finish(self) {
+        if self.format == OutputFormat::Json {
+            if self.count == 0 {
+                println!("[]");
+            } else {
+                println!("\n]");
+            }
+        }
+    }
+}
+
+impl<D: Dependency> Sink<D> for Printer {
+    fn emit(&mut self, dependency: D) {
+        let dependency = dependency.with_path_style(self.path_style);
+        if self.format == OutputFormat::Text {
+            println!("{}", dependency.to_text());
+            self.count += 1;
+        } else {
+            self.write_json(dependency.to_json());
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Stripped partitions of documents used to check functional dependencies
+//!
+//! A partition groups documents into equivalence classes which share the
+//! same values for a set of paths. As in TANE, classes with a single
+//! document are stripped since they can never violate a dependency. A
+//! document with several values at a path (e.g. in an array) may belong
+//! to more than one class, in which case two documents agree on the path
+//! when any class contains both of them.
+
+use std::collections::HashMap;
+
+use roaring::bitmap::RoaringBitmap;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Partition {
+    classes: Vec<Vec<u32>>,
+    /// Whether any document appears in more than one class
+    overlapping: bool,
+}
+
+/// A lookup table from each document to the classes containing it
+struct Membership {
+    offsets: Vec<usize>,
+    classes: Vec<u32>,
+}
+
+impl Membership {
+    fn classes_of(&self, row: u32) -> &[u32] {
+        let row = row as usize;
+        if row + 1 >= self.offsets.len() {
+            &[]
+        } else {
+            &self.classes[self.offsets[row]..self.offsets[row + 1]]
+        }
+    }
+}
+
+impl Partition {
+    /// Build a partition from groups of documents, stripping any
+    /// groups which contain fewer than two documents
+    pub fn new<I>(classes: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u32>>,
+    {
+        let mut seen = RoaringBitmap::new();
+        let mut overlapping = false;
+        let classes = classes
+            .into_iter()
+            .filter(|class| class.len() > 1)
+            .map(|mut class| {
+                class.sort_unstable();
+                for row in &class {
+                    overlapping |= !seen.insert(*row);
+                }
+
+                class
+            })
+            .collect();
+
+        Partition {
+            classes,
+            overlapping,
+        }
+    }
+
+    /// The partition with every document in a single class
+    pub fn all(rows: u32) -> Self {
+        Partition::new([(0..rows).collect()])
+    }
+
+    /// Build a partition of documents by value from
+    /// a map of values to the documents containing them
+    pub fn from_values(values: &HashMap<usize, RoaringBitmap>) -> Self {
+        Partition::new(values.values().map(|rows| rows.iter().collect()))
+    }
+
+    fn membership(&self) -> Membership {
+        let rows = self
+            .classes
+            .iter()
+            .filter_map(|class| class.last())
+            .max()
+            .map_or(0, |max| *max as usize + 1);
+
+        // Count the classes for each document and convert
+        // the counts to offsets into a flat list of classes
+        let mut offsets = vec![0; rows + 1];
+        for row in self.classes.iter().flatten() {
+            offsets[*row as usize + 1] += 1;
+        }
+        for i in 1..offsets.len() {
+            offsets[i] += offsets[i - 1];
+        }
+
+        let mut next = offsets.clone();
+        let mut classes = vec![0; offsets[rows]];
+        for (i, class) in self.classes.iter().enumerate() {
+            for row in class {
+                classes[next[*row as usize]] = i as u32;
+                next[*row as usize] += 1;
+            }
+        }
+
+        Membership { offsets, classes }
+    }
+
+    /// Compute the partition for the union of the paths of both partitions
+    pub fn product(&self, other: &Partition) -> Partition {
+        let membership = other.membership();
+        let mut groups = vec![Vec::new(); other.classes.len()];
+        let mut touched = Vec::new();
+        let mut classes = Vec::new();
+
+        for class in &self.classes {
+            // Split each class by the classes of the other partition
+            for row in class {
+                for i in membership.classes_of(*row) {
+                    let group = &mut groups[*i as usize];
+                    if group.is_empty() {
+                        touched.push(*i as usize);
+                    }
+                    group.push(*row);
+                }
+            }
+
+            for i in touched.drain(..) {
+                let group = std::mem::take(&mut groups[i]);
+                if group.len() > 1 {
+                    classes.push(group);
+                }
+            }
+        }
+
+        // Overlapping classes can produce the same group more than once
+        let overlapping = self.overlapping || other.overlapping;
+        if overlapping {
+            classes.sort_unstable();
+            classes.dedup();
+        }
+
+        Partition {
+            classes,
+            overlapping,
+        }
+    }
+
+    /// The number of documents which must be removed so that no
+    /// two documents are equivalent (i.e. the paths form a key)
+    pub fn key_error(&self) -> u64 {
+        self.classes
+            .iter()
+            .map(|class| class.len() as u64 - 1)
+            .sum()
+    }
+
+    /// The number of documents which must be removed for the paths of
+    /// this partition to determine the paths of the `rhs` partition
+    ///
+    /// This is the g3 error measure from TANE and is zero exactly
+    /// when every pair of equivalent documents also agrees on `rhs`.
+    pub fn error(&self, rhs: &Partition) -> u64 {
+        let membership = rhs.membership();
+        let mut counts = vec![0; rhs.classes.len()];
+        let mut touched = Vec::new();
+        let mut violations = 0;
+
+        for class in &self.classes {
+            // Find the largest group of documents in
+            // this class which also agree on the rhs
+            let mut largest = 1;
+            for row in class {
+                for i in membership.classes_of(*row) {
+                    let count = &mut counts[*i as usize];
+                    if *count == 0 {
+                        touched.push(*i as usize);
+                    }
+                    *count += 1;
+                    largest = largest.max(*count);
+                }
+            }
+
+            for i in touched.drain(..) {
+                counts[i] = 0;
+            }
+
+            violations += (class.len() - largest) as u64;
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_strips_singleton_classes() {
+        let partition = Partition::new([vec![3, 1], vec![2], vec![0, 4]]);
+
+        assert_eq!(partition.classes, vec![vec![1, 3], vec![0, 4]]);
+        assert_eq!(partition.key_error(), 2);
+    }
+
+    #[test]
+    fn it_computes_products() {
+        let x = Partition::new([vec![0, 1, 2], vec![3, 4]]);
+        let y = Partition::new([vec![0, 1, 3, 4]]);
+
+        assert_eq!(x.product(&y), Partition::new([vec![0, 1], vec![3, 4]]));
+        assert!(x.product(&Partition::default()).classes.is_empty());
+    }
+
+    #[test]
+    fn it_computes_products_of_overlapping_classes() {
+        let x = Partition::new([vec![0, 1, 2], vec![0, 1, 3]]);
+        let y = Partition::new([vec![0, 1]]);
+
+        assert_eq!(x.product(&y).classes, vec![vec![0, 1]]);
+    }
+
+    #[test]
+    fn it_computes_the_error_of_a_dependency() {
+        let x = Partition::new([vec![0, 1, 2, 3], vec![4, 5]]);
+        let a = Partition::new([vec![0, 1, 2], vec![4, 5]]);
+
+        assert_eq!(x.error(&a), 1);
+        assert_eq!(a.error(&x), 0);
+        assert_eq!(Partition::all(6).error(&a), 3);
+    }
+}
@@ -0,0 +1,387 @@
+//! Representation of paths to values within documents
+//!
+//! A [`Path`] is a sequence of segments which is built up while
+//! documents are traversed and only written as a string for output.
+//! Paths are written with keys separated by dots and array elements
+//! written as `[*]` (e.g. `a.b[*].c`). Keys which are empty or contain
+//! any of `.[]"\` are written as a quoted JSON string within brackets
+//! (e.g. `a["b.c"]`) so that every path has a single meaning. Elements
+//! at a particular position in an array are written as `[0]`. Paths
+//! can also be parsed from this syntax and written in others.
+
+use std::fmt;
+
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+/// Syntax used when writing paths in discovered dependencies
+///
+/// Dotted paths are the same as those used internally. JSONPath and jq
+/// paths select all values at the path. JSON Pointer (RFC 6901) paths
+/// have no syntax for all elements of an array, so these are written
+/// as `*`.
+pub enum PathStyle {
+    #[default]
+    Dotted,
+    Jsonpath,
+    Pointer,
+    Jq,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+/// How the elements of arrays are represented in paths
+///
+/// Wildcard paths treat each array as a collection of values, while
+/// indexed paths treat each position in an array separately. Auto uses
+/// indexed paths for arrays at a path which always have the same
+/// length of at most [`MAX_TUPLE_LENGTH`] and wildcards otherwise.
+pub enum ArrayMode {
+    #[default]
+    Wildcard,
+    Indexed,
+    Auto,
+}
+
+/// The longest array treated as a tuple when using [`ArrayMode::Auto`]
+pub const MAX_TUPLE_LENGTH: usize = 4;
+
+/// A single step in a path
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Key(String),
+    /// The element at a position in an array
+    Index(usize),
+    /// Any element of an array
+    Element,
+}
+
+/// Check if a key must be quoted to avoid ambiguity
+fn needs_quotes(key: &str) -> bool {
+    key.is_empty() || key.contains(['.', '[', ']', '"', '\\'])
+}
+
+/// Append a key within an object to a written path
+fn push_key(path: &mut String, key: &str) {
+    if needs_quotes(key) {
+        path.push('[');
+        path.push_str(&json::stringify(key));
+        path.push(']');
+    } else {
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(key);
+    }
+}
+
+/// Append the elements of an array to a written path
+fn push_element(path: &mut String) {
+    path.push_str("[*]");
+}
+
+/// Append the element at a position in an array to a written path
+fn push_index(path: &mut String, index: usize) {
+    path.push_str(&format!("[{}]", index));
+}
+
+/// The paths of the element at a position in an array, which
+/// has both an indexed and a wildcard path in auto mode
+pub fn element_paths(mode: ArrayMode, path: &Path, index: usize, len: usize) -> Vec<Path> {
+    let mut paths = Vec::new();
+    if mode == ArrayMode::Indexed || (mode == ArrayMode::Auto && len <= MAX_TUPLE_LENGTH) {
+        paths.push(path.child(Segment::Index(index)));
+    }
+    if mode != ArrayMode::Indexed {
+        paths.push(path.child(Segment::Element));
+    }
+
+    paths
+}
+
+/// A path to values within documents as a sequence of segments
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    /// The path of a key within the object at this path
+    pub fn key(&self, key: &str) -> Self {
+        self.child(Segment::Key(key.to_owned()))
+    }
+
+    /// The path of a segment below this path
+    pub fn child(&self, segment: Segment) -> Self {
+        let mut path = self.clone();
+        path.segments.push(segment);
+
+        path
+    }
+
+    /// Combine this path with another path relative to it
+    pub fn join(&self, path: &Path) -> Self {
+        let mut joined = self.clone();
+        joined.segments.extend_from_slice(&path.segments);
+
+        joined
+    }
+
+    /// Whether this path refers to a whole document
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Whether the last segment of the path is any element of an array
+    pub fn is_element(&self) -> bool {
+        self.segments.last() == Some(&Segment::Element)
+    }
+
+    /// Split a path into its segments
+    ///
+    /// Any malformed part of the path is treated as a key.
+    pub fn parse(path: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut rest = path;
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("[*]") {
+                segments.push(Segment::Element);
+                rest = after;
+            } else if let Some((index, after)) = parse_index(rest) {
+                segments.push(Segment::Index(index));
+                rest = after;
+            } else if let Some((key, after)) = parse_quoted(rest) {
+                segments.push(Segment::Key(key));
+                rest = after;
+            } else {
+                let rest_key = rest.strip_prefix('.').unwrap_or(rest);
+                let end = rest_key[1.min(rest_key.len())..]
+                    .find(['.', '['])
+                    .map_or(rest_key.len(), |i| i + 1);
+                segments.push(Segment::Key(rest_key[..end].to_string()));
+                rest = &rest_key[end..];
+            }
+        }
+
+        Path { segments }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Write the path using a particular syntax
+    pub fn format(&self, style: PathStyle) -> String {
+        let segments = self.segments.iter();
+        match style {
+            PathStyle::Dotted => self.to_string(),
+            PathStyle::Jsonpath => std::iter::once("$".to_string())
+                .chain(segments.map(|segment| match segment {
+                    Segment::Key(key) if is_identifier(key) => format!(".{}", key),
+                    Segment::Key(key) => {
+                        format!("['{}']", key.replace('\\', "\\\\").replace('\'', "\\'"))
+                    }
+                    Segment::Index(index) => format!("[{}]", index),
+                    Segment::Element => "[*]".to_string(),
+                }))
+                .collect(),
+            PathStyle::Pointer => segments
+                .map(|segment| match segment {
+                    Segment::Key(key) => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+                    Segment::Index(index) => format!("/{}", index),
+                    Segment::Element => "/*".to_string(),
+                })
+                .collect(),
+            PathStyle::Jq => {
+                let path: String = segments
+                    .map(|segment| match segment {
+                        Segment::Key(key) if is_identifier(key) => format!(".{}", key),
+                        Segment::Key(key) => format!(".{}", json::stringify(key.as_str())),
+                        Segment::Index(index) => format!("[{}]", index),
+                        Segment::Element => "[]".to_string(),
+                    })
+                    .collect();
+
+                // The identity filter is needed before an initial array
+                if path.is_empty() || path.starts_with('[') {
+                    format!(".{}", path)
+                } else {
+                    path
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut path = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Key(key) => push_key(&mut path, key),
+                Segment::Index(index) => push_index(&mut path, *index),
+                Segment::Element => push_element(&mut path),
+            }
+        }
+
+        write!(f, "{}", path)
+    }
+}
+
+/// Check if a key can be written without quotes in JSONPath and jq
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse an array index in brackets from the start of a path
+fn parse_index(path: &str) -> Option<(usize, &str)> {
+    let (index, after) = path.strip_prefix('[')?.split_once(']')?;
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    Some((index.parse().ok()?, after))
+}
+
+/// Parse a quoted key in brackets from the start of a path
+fn parse_quoted(path: &str) -> Option<(String, &str)> {
+    let quoted = path.strip_prefix("[\"")?;
+
+    // Find the closing quote, skipping any escaped characters
+    let mut escaped = false;
+    let end = quoted.char_indices().find_map(|(i, c)| {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(i),
+            _ => escaped = false,
+        }
+        None
+    })?;
+
+    let key = json::parse(&path[1..end + 3]).ok()?;
+    let after = quoted[end + 1..].strip_prefix(']')?;
+
+    Some((key.as_str()?.to_string(), after))
+}
+
+/// Write a path using a particular syntax
+pub fn format(path: &str, style: PathStyle) -> String {
+    match style {
+        // Paths are already stored in the dotted syntax
+        PathStyle::Dotted => path.to_string(),
+        _ => Path::parse(path).format(style),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(keys: &[&str]) -> String {
+        let mut path = String::new();
+        for key in keys {
+            if *key == "[*]" {
+                push_element(&mut path);
+            } else {
+                push_key(&mut path, key);
+            }
+        }
+
+        path
+    }
+
+    #[test]
+    fn it_quotes_ambiguous_keys() {
+        assert_eq!(path(&["a", "b", "[*]", "c"]), "a.b[*].c");
+        assert_eq!(path(&["a.b"]), "[\"a.b\"]");
+        assert_eq!(path(&["a", "", "[*]"]), "a[\"\"][*]");
+        assert_ne!(path(&["a", "b"]), path(&["a.b"]));
+    }
+
+    #[test]
+    fn it_parses_paths() {
+        assert_eq!(
+            Path::parse("a[\"b.c\"][*].d").segments(),
+            [
+                Segment::Key("a".to_string()),
+                Segment::Key("b.c".to_string()),
+                Segment::Element,
+                Segment::Key("d".to_string()),
+            ]
+        );
+        assert_eq!(
+            Path::parse("[\"\\\"]\"]").segments(),
+            [Segment::Key("\"]".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_joins_paths() {
+        let orders = Path::default().key("orders");
+
+        assert_eq!(orders.join(&Path::parse("id")).to_string(), "orders.id");
+        assert_eq!(
+            orders.join(&Path::default().key("a.b")).to_string(),
+            "orders[\"a.b\"]"
+        );
+        assert_eq!(Path::default().join(&orders).to_string(), "orders");
+    }
+
+    #[test]
+    fn it_writes_parsed_paths_unchanged() {
+        for path in [
+            "a.b[*].c",
+            "[\"\"]",
+            "a[\"b.c\"][*][*]",
+            "[*].a",
+            "a[0][1].b",
+        ] {
+            assert_eq!(Path::parse(path).to_string(), path);
+        }
+    }
+
+    #[test]
+    fn it_builds_element_paths_for_each_mode() {
+        let element_paths = |mode, index, len| -> Vec<String> {
+            element_paths(mode, &Path::parse("a"), index, len)
+                .iter()
+                .map(Path::to_string)
+                .collect()
+        };
+
+        assert_eq!(element_paths(ArrayMode::Wildcard, 1, 2), vec!["a[*]"]);
+        assert_eq!(element_paths(ArrayMode::Indexed, 1, 10), vec!["a[1]"]);
+        assert_eq!(element_paths(ArrayMode::Auto, 1, 2), vec!["a[1]", "a[*]"]);
+        assert_eq!(element_paths(ArrayMode::Auto, 1, 10), vec!["a[*]"]);
+    }
+
+    #[test]
+    fn it_formats_jsonpath() {
+        assert_eq!(format("a[*].b", PathStyle::Jsonpath), "$.a[*].b");
+        assert_eq!(
+            format(&path(&["a.b", "it's"]), PathStyle::Jsonpath),
+            "$['a.b']['it\\'s']"
+        );
+    }
+
+    #[test]
+    fn it_formats_jq() {
+        assert_eq!(format("a[*].b", PathStyle::Jq), ".a[].b");
+        assert_eq!(format("[*].a", PathStyle::Jq), ".[].a");
+        assert_eq!(
+            format(&path(&["a.b", "1"]), PathStyle::Jq),
+            ".\"a.b\".\"1\""
+        );
+    }
+
+    #[test]
+    fn it_formats_json_pointers() {
+        assert_eq!(format("a[*].b", PathStyle::Pointer), "/a/*/b");
+        assert_eq!(format("a[1].b", PathStyle::Pointer), "/a/1/b");
+        assert_eq!(format(&path(&["a/b", "~"]), PathStyle::Pointer), "/a~1b/~0");
+        assert_eq!(format(&path(&["a.b", ""]), PathStyle::Pointer), "/a.b/");
+    }
+}
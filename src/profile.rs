@@ -0,0 +1,292 @@
+//! Profiling of the values at each path to skip paths
+//! which are unlikely to produce meaningful dependencies
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use clap::ValueEnum;
+
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::path::Path;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+/// What happens to paths which fail the profiling thresholds
+pub enum ProfileAction {
+    #[default]
+    Exclude,
+    Flag,
+}
+
+/// Thresholds used to decide whether a path is worth considering
+///
+/// Thresholds which are not set are never checked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileOptions {
+    /// Largest allowed ratio of distinct values to occurrences
+    pub max_distinct_ratio: Option<f64>,
+    /// Largest allowed average length of string values
+    pub max_avg_length: Option<f64>,
+    /// Smallest allowed number of distinct values
+    pub min_distinct: Option<u64>,
+    /// Largest allowed number of distinct values
+    pub max_distinct: Option<u64>,
+    pub action: ProfileAction,
+}
+
+/// A summary of the values observed at a path
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct PathProfile {
+    /// Number of values observed, including duplicates
+    pub count: u64,
+    /// Number of string values observed
+    pub strings: u64,
+    /// Total number of characters in all string values
+    pub string_length: u64,
+}
+
+/// The reason a path failed a profiling threshold
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rejection {
+    DistinctRatio { ratio: f64, limit: f64 },
+    AverageLength { length: f64, limit: f64 },
+    TooFewDistinct { distinct: u64, limit: u64 },
+    TooManyDistinct { distinct: u64, limit: u64 },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::DistinctRatio { ratio, limit } => {
+                write!(f, "distinct ratio {:.3} is above {}", ratio, limit)
+            }
+            Rejection::AverageLength { length, limit } => {
+                write!(f, "average length {:.1} is above {}", length, limit)
+            }
+            Rejection::TooFewDistinct { distinct, limit } => {
+                write!(f, "{} distinct values is below {}", distinct, limit)
+            }
+            Rejection::TooManyDistinct { distinct, limit } => {
+                write!(f, "{} distinct values is above {}", distinct, limit)
+            }
+        }
+    }
+}
+
+impl ProfileOptions {
+    /// Whether any thresholds are set
+    pub fn is_enabled(&self) -> bool {
+        self.max_distinct_ratio.is_some()
+            || self.max_avg_length.is_some()
+            || self.min_distinct.is_some()
+            || self.max_distinct.is_some()
+    }
+
+    /// Check the profile of a path with a given number
+    /// of distinct values against each threshold
+    pub(crate) fn check(&self, profile: &PathProfile, distinct: u64) -> Option<Rejection> {
+        if let Some(limit) = self.min_distinct {
+            if distinct < limit {
+                return Some(Rejection::TooFewDistinct { distinct, limit });
+            }
+        }
+        if let Some(limit) = self.max_distinct {
+            if distinct > limit {
+                return Some(Rejection::TooManyDistinct { distinct, limit });
+            }
+        }
+        if let Some(limit) = self.max_distinct_ratio {
+            let ratio = distinct as f64 / profile.count.max(1) as f64;
+            if ratio > limit {
+                return Some(Rejection::DistinctRatio { ratio, limit });
+            }
+        }
+        if let Some(limit) = self.max_avg_length {
+            if profile.strings > 0 {
+                let length = profile.string_length as f64 / profile.strings as f64;
+                if length > limit {
+                    return Some(Rejection::AverageLength { length, limit });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Check each path against the profiling thresholds, reporting any
+/// which fail as diagnostics and removing them if they should be excluded
+///
+/// Returns every path which failed along with the reason, sorted by path.
+pub(crate) fn screen_paths<T, F>(
+    options: &ProfileOptions,
+    profiles: &HashMap<Path, PathProfile>,
+    paths: &mut HashMap<Path, T>,
+    distinct: F,
+    diagnostics: &Diagnostics,
+) -> Vec<(Path, Rejection)>
+where
+    F: Fn(&T) -> u64,
+{
+    if !options.is_enabled() {
+        return Vec::new();
+    }
+
+    let mut rejected: Vec<_> = paths
+        .iter()
+        .filter_map(|(path, values)| {
+            let profile = profiles.get(path).cloned().unwrap_or_default();
+            options
+                .check(&profile, distinct(values))
+                .map(|rejection| (path.clone(), rejection))
+        })
+        .collect();
+    rejected.sort_by_cached_key(|(path, _)| path.to_string());
+
+    for (path, rejection) in &rejected {
+        let message = match options.action {
+            ProfileAction::Exclude => {
+                paths.remove(path);
+                format!("Excluding {} since {}", path, rejection)
+            }
+            ProfileAction::Flag => {
+                format!(
+                    "Warning: {} may not be meaningful since {}",
+                    path, rejection
+                )
+            }
+        };
+        diagnostics.report(Diagnostic::Warning(message));
+    }
+
+    rejected
+}
+
+/// Select the paths of a dependency which were flagged when screening
+pub(crate) fn flagged_paths<'a, I>(flagged: &HashSet<String>, paths: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    paths
+        .into_iter()
+        .filter(|path| flagged.contains(*path))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    fn profile(count: u64, strings: u64, string_length: u64) -> PathProfile {
+        PathProfile {
+            count,
+            strings,
+            string_length,
+        }
+    }
+
+    #[test]
+    fn it_checks_distinct_values() {
+        let options = ProfileOptions {
+            max_distinct_ratio: Some(0.9),
+            min_distinct: Some(2),
+            ..ProfileOptions::default()
+        };
+
+        assert_eq!(options.check(&profile(10, 0, 0), 5), None);
+        assert_eq!(
+            options.check(&profile(10, 0, 0), 10),
+            Some(Rejection::DistinctRatio {
+                ratio: 1.0,
+                limit: 0.9
+            })
+        );
+        assert_eq!(
+            options.check(&profile(10, 0, 0), 1),
+            Some(Rejection::TooFewDistinct {
+                distinct: 1,
+                limit: 2
+            })
+        );
+    }
+
+    #[test]
+    fn it_checks_the_average_string_length() {
+        let options = ProfileOptions {
+            max_avg_length: Some(10.0),
+            ..ProfileOptions::default()
+        };
+
+        assert_eq!(options.check(&profile(4, 2, 20), 2), None);
+        assert!(options.check(&profile(4, 2, 30), 2).is_some());
+    }
+
+    #[test]
+    fn it_excludes_or_flags_paths() {
+        let (a, b) = (Path::parse("a"), Path::parse("b"));
+        let profiles = HashMap::from([(a.clone(), profile(3, 0, 0))]);
+        let mut paths = HashMap::from([(a.clone(), 3), (b.clone(), 1)]);
+        let mut options = ProfileOptions {
+            max_distinct: Some(2),
+            action: ProfileAction::Flag,
+            ..ProfileOptions::default()
+        };
+
+        let rejected = screen_paths(
+            &options,
+            &profiles,
+            &mut paths,
+            |n| *n,
+            &Diagnostics::default(),
+        );
+        assert_eq!(paths.len(), 2);
+        assert_eq!(
+            rejected,
+            vec![(
+                a,
+                Rejection::TooManyDistinct {
+                    distinct: 3,
+                    limit: 2
+                }
+            )]
+        );
+
+        options.action = ProfileAction::Exclude;
+        let rejected = screen_paths(
+            &options,
+            &profiles,
+            &mut paths,
+            |n| *n,
+            &Diagnostics::default(),
+        );
+        assert_eq!(paths.keys().collect::<Vec<_>>(), vec![&b]);
+        assert_eq!(rejected.len(), 1);
+    }
+
+    #[test]
+    fn it_reports_screened_paths_as_diagnostics() {
+        let a = Path::parse("a");
+        let profiles = HashMap::from([(a.clone(), profile(3, 0, 0))]);
+        let mut paths = HashMap::from([(a, 3)]);
+        let options = ProfileOptions {
+            max_distinct: Some(2),
+            ..ProfileOptions::default()
+        };
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let diagnostics = {
+            let reported = reported.clone();
+            Diagnostics::new(move |diagnostic| reported.lock().unwrap().push(diagnostic.clone()))
+        };
+        screen_paths(&options, &profiles, &mut paths, |n| *n, &diagnostics);
+
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec![Diagnostic::Warning(
+                "Excluding a since 3 distinct values is above 2".to_string()
+            )]
+        );
+    }
+}
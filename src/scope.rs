@@ -0,0 +1,187 @@
+//! Discovery within nested arrays instead of whole documents
+//!
+//! A scope is a path such as `items[*]` whose values each become a
+//! separate row for discovery. Each row is made up of the values it
+//! contains along with their paths in the document, so paths are the
+//! same as without a scope. Other elements of the arrays along the
+//! path are not part of the row, and the fields of the enclosing
+//! objects are only included if they are inherited.
+
+use json::JsonValue;
+
+use crate::input::{Located, Origin};
+use crate::path::{Path, Segment};
+
+/// The values in a row along with the path of each in the document
+pub type Row = Vec<(Path, JsonValue)>;
+
+/// A path whose values are used as rows instead of whole documents
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scope {
+    path: Path,
+    inherit: bool,
+}
+
+impl Scope {
+    pub fn new(path: &str) -> Self {
+        Scope {
+            path: Path::parse(path),
+            inherit: false,
+        }
+    }
+
+    /// Include the fields of objects enclosing the scope in each row
+    pub fn inherit(mut self, inherit: bool) -> Self {
+        self.inherit = inherit;
+        self
+    }
+
+    /// Split a document into a row for each value in the scope
+    pub fn rows(&self, document: &JsonValue) -> Vec<Row> {
+        rows_at(
+            document,
+            Path::default(),
+            self.path.segments(),
+            self.inherit,
+        )
+    }
+}
+
+fn rows_at(value: &JsonValue, path: Path, segments: &[Segment], inherit: bool) -> Vec<Row> {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return vec![vec![(path, value.clone())]],
+    };
+
+    match (segment, value) {
+        (Segment::Key(key), JsonValue::Object(obj)) => match obj.get(key) {
+            Some(child) => {
+                let mut rows = rows_at(child, path.key(key), rest, inherit);
+                if inherit {
+                    let fields = obj.iter().filter(|(k, _)| k != key);
+                    let fields: Row = fields.map(|(k, v)| (path.key(k), v.clone())).collect();
+                    for row in &mut rows {
+                        row.extend(fields.iter().cloned());
+                    }
+                }
+
+                rows
+            }
+            None => vec![],
+        },
+        (Segment::Element, JsonValue::Array(arr)) => arr
+            .iter()
+            .flat_map(|child| rows_at(child, path.child(Segment::Element), rest, inherit))
+            .collect(),
+        (Segment::Index(index), JsonValue::Array(arr)) if *index < arr.len() => {
+            // Paths within the array keep the position of each element
+            let mut rows = rows_at(&arr[*index], path.child(segment.clone()), rest, inherit);
+            if inherit {
+                let others = arr.iter().enumerate().filter(|(i, _)| i != index);
+                let others: Row = others
+                    .map(|(i, v)| (path.child(Segment::Index(i)), v.clone()))
+                    .collect();
+                for row in &mut rows {
+                    row.extend(others.iter().cloned());
+                }
+            }
+
+            rows
+        }
+        _ => vec![],
+    }
+}
+
+/// Replace each document with its rows in a scope, or
+/// a single row containing the whole document if there is none
+pub(crate) fn scoped_documents<'a, I, E>(
+    scope: &'a Option<Scope>,
+    documents: I,
+) -> impl Iterator<Item = Result<(Origin, Row), E>> + 'a
+where
+    I: IntoIterator<Item = Result<Located, E>>,
+    I::IntoIter: 'a,
+    E: 'a,
+{
+    documents
+        .into_iter()
+        .flat_map(move |document| match (document, scope) {
+            (Ok((origin, document)), Some(scope)) => scope
+                .rows(&document)
+                .into_iter()
+                .map(|row| Ok((origin.clone(), row)))
+                .collect(),
+            (Ok((origin, document)), None) => vec![Ok((origin, vec![(Path::default(), document)]))],
+            (Err(error), _) => vec![Err(error)],
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use json::object;
+
+    fn row(values: Vec<(&str, JsonValue)>) -> Row {
+        values
+            .into_iter()
+            .map(|(path, value)| (Path::parse(path), value))
+            .collect()
+    }
+
+    #[test]
+    fn it_splits_documents_into_rows() {
+        let doc = object! {id: 1, items: [{sku: "a"}, {sku: "b"}]};
+        let rows = Scope::new("items[*]").rows(&doc);
+
+        assert_eq!(
+            rows,
+            vec![
+                row(vec![("items[*]", object! {sku: "a"})]),
+                row(vec![("items[*]", object! {sku: "b"})])
+            ]
+        );
+    }
+
+    #[test]
+    fn it_inherits_enclosing_fields() {
+        let doc = object! {id: 1, orders: [{n: 1, items: [1, 2]}, {n: 2, items: [3]}]};
+        let rows = Scope::new("orders[*].items[*]").inherit(true).rows(&doc);
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[1],
+            row(vec![
+                ("orders[*].items[*]", 2.into()),
+                ("orders[*].n", 1.into()),
+                ("id", 1.into())
+            ])
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_position_of_indexed_elements() {
+        let doc = object! {p: [1, 2, 3]};
+
+        assert_eq!(
+            Scope::new("p[1]").rows(&doc),
+            vec![row(vec![("p[1]", 2.into())])]
+        );
+        assert_eq!(
+            Scope::new("p[1]").inherit(true).rows(&doc),
+            vec![row(vec![
+                ("p[1]", 2.into()),
+                ("p[0]", 1.into()),
+                ("p[2]", 3.into())
+            ])]
+        );
+    }
+
+    #[test]
+    fn it_skips_documents_without_the_scope() {
+        let scope = Scope::new("items[*]");
+
+        assert!(scope.rows(&object! {items: 1}).is_empty());
+        assert!(scope.rows(&object! {other: [1]}).is_empty());
+    }
+}
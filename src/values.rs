@@ -0,0 +1,506 @@
+//! Interning of JSON values shared by all types of discovery
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use clap::ValueEnum;
+use json::number::Number;
+use json::JsonValue;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::filter::PathFilter;
+use crate::flatten::ExpansionLimit;
+use crate::path::{self, ArrayMode, Path, Segment};
+use crate::profile::{PathProfile, ProfileOptions};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+/// How null values are compared with each other
+///
+/// By default, null values are ignored the same as missing values.
+/// Otherwise, they are either all equal or each distinct from every
+/// other value.
+pub enum NullSemantics {
+    #[default]
+    Ignore,
+    NullEqualsNull,
+    NullNotEqualsNull,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+/// A step used to normalize values before they are compared
+///
+/// Steps are always applied in the order they are listed here,
+/// regardless of the order in which they are given.
+pub enum Normalize {
+    Trim,
+    Nfc,
+    CaseFold,
+    Coerce,
+    Numbers,
+}
+
+/// The normalization steps applied to the values at a path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// Remove leading and trailing whitespace from strings
+    pub trim: bool,
+    /// Convert strings to Unicode normalization form C
+    pub nfc: bool,
+    /// Fold the case of strings to compare them without regard to case
+    pub case_fold: bool,
+    /// Convert strings containing a number to that number
+    pub coerce: bool,
+    /// Give numbers with the same value the same representation
+    pub numbers: bool,
+}
+
+impl Normalization {
+    /// Build a normalization from a list of steps
+    pub fn from_steps(steps: &[Normalize]) -> Self {
+        Normalization {
+            trim: steps.contains(&Normalize::Trim),
+            nfc: steps.contains(&Normalize::Nfc),
+            case_fold: steps.contains(&Normalize::CaseFold),
+            coerce: steps.contains(&Normalize::Coerce),
+            numbers: steps.contains(&Normalize::Numbers),
+        }
+    }
+
+    /// Whether this normalization leaves all values unchanged
+    pub fn is_identity(&self) -> bool {
+        *self == Normalization::default()
+    }
+
+    /// Normalize a single value which does not contain other values
+    pub fn apply(&self, value: &JsonValue) -> JsonValue {
+        let mut value = value.clone();
+
+        if let Some(s) = value.as_str() {
+            let mut s = s.to_owned();
+            if self.trim {
+                s = s.trim().to_owned();
+            }
+            if self.nfc {
+                s = s.nfc().collect();
+            }
+            if self.case_fold {
+                s = case_fold(&s);
+            }
+
+            value = match json::parse(&s) {
+                Ok(number) if self.coerce && number.is_number() => number,
+                _ => s.into(),
+            };
+        }
+
+        if self.numbers {
+            if let JsonValue::Number(number) = value {
+                value = canonical_number(number);
+            }
+        }
+
+        value
+    }
+}
+
+/// Fold the case of a string so that strings which differ only in case,
+/// including characters such as `ß` which fold to several (`ss`), are equal
+fn case_fold(s: &str) -> String {
+    // Characters with no single lowercase form expand when uppercased
+    s.to_lowercase().to_uppercase().to_lowercase()
+}
+
+/// Give a number a single representation for its value
+///
+/// This works on the decimal digits of the number rather than converting
+/// to a float so that large integers such as IDs are never rounded.
+fn canonical_number(number: Number) -> JsonValue {
+    let (positive, mut mantissa, mut exponent) = number.as_parts();
+    if number.is_nan() {
+        return number.into();
+    } else if mantissa == 0 {
+        return 0.into();
+    }
+
+    // Remove trailing zeros so equal numbers have the same digits
+    while mantissa % 10 == 0 && exponent < i16::MAX {
+        mantissa /= 10;
+        exponent += 1;
+    }
+
+    // Integral values are written without an exponent when they fit
+    while exponent > 0 {
+        match mantissa.checked_mul(10) {
+            Some(shifted) => {
+                mantissa = shifted;
+                exponent -= 1;
+            }
+            None => break,
+        }
+    }
+
+    Number::from_parts(positive, mantissa, exponent).into()
+}
+
+/// Options controlling which values are considered during discovery
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValueOptions {
+    pub nulls: NullSemantics,
+    /// Treat empty strings, arrays, and objects as values instead of ignoring them
+    pub keep_empty: bool,
+    /// Normalization applied to values at all paths
+    pub normalize: Normalization,
+    /// Normalization applied instead of the default at specific paths
+    pub path_normalize: HashMap<Path, Normalization>,
+    /// Paths whose values are considered
+    pub paths: PathFilter,
+    /// Thresholds for skipping paths based on their values
+    pub profile: ProfileOptions,
+    /// How the elements of arrays are represented in paths
+    pub arrays: ArrayMode,
+    /// Limit on the values each document is flattened into in static mode
+    pub expansion: Option<ExpansionLimit>,
+}
+
+impl ValueOptions {
+    /// Find the normalization used for values at a path
+    pub fn normalization(&self, path: &Path) -> &Normalization {
+        self.path_normalize.get(path).unwrap_or(&self.normalize)
+    }
+}
+
+/// Assigns an index to each distinct value observed in the input
+#[derive(Debug, Default)]
+pub(crate) struct Values {
+    pub indices: HashMap<Rc<str>, usize>,
+    /// Each distinct value by its index, shared with `indices`
+    by_index: Vec<Rc<str>>,
+    /// Summaries of the values at each path (only if profiling)
+    pub profiles: HashMap<Path, PathProfile>,
+    /// The length of arrays at each path, or `None` if the
+    /// length varies (only in auto array mode)
+    array_lengths: HashMap<Path, Option<usize>>,
+    /// Whether each path is allowed by the path filter
+    allowed: HashMap<Path, bool>,
+    /// Whether each path is excluded by the path filter
+    excluded: HashMap<Path, bool>,
+    count: usize,
+    options: ValueOptions,
+}
+
+impl Values {
+    pub fn new(options: ValueOptions) -> Self {
+        Values {
+            options,
+            ..Values::default()
+        }
+    }
+
+    /// The number of distinct values
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// The options used to select and compare values
+    pub fn options(&self) -> &ValueOptions {
+        &self.options
+    }
+
+    /// Whether no values at a path or any path below it are considered
+    pub fn is_excluded(&mut self, path: &Path) -> bool {
+        if let Some(excluded) = self.excluded.get(path) {
+            return *excluded;
+        }
+
+        let excluded = self.options.paths.is_excluded(path);
+        self.excluded.insert(path.clone(), excluded);
+
+        excluded
+    }
+
+    /// Whether the values at a path are considered
+    fn allows(&mut self, path: &Path) -> bool {
+        if let Some(allowed) = self.allowed.get(path) {
+            return *allowed;
+        }
+
+        let allowed = self.options.paths.allows(path);
+        self.allowed.insert(path.clone(), allowed);
+
+        allowed
+    }
+
+    /// The paths of the element at a position in an array
+    pub fn element_paths(&self, path: &Path, index: usize, len: usize) -> Vec<Path> {
+        path::element_paths(self.options.arrays, path, index, len)
+    }
+
+    /// Record the length of all arrays in a value so
+    /// tuples can be identified in auto array mode
+    pub fn record_arrays(&mut self, path: &Path, value: &JsonValue) {
+        if self.options.arrays != ArrayMode::Auto {
+            return;
+        }
+
+        if value.is_object() {
+            for (key, child) in value.entries() {
+                self.record_arrays(&path.key(key), child);
+            }
+        } else if value.is_array() {
+            let len = value.len();
+            self.array_lengths
+                .entry(path.clone())
+                .and_modify(|length| *length = length.filter(|l| *l == len))
+                .or_insert(Some(len));
+
+            for (i, child) in value.members().enumerate() {
+                for new_path in self.element_paths(path, i, len) {
+                    self.record_arrays(&new_path, child);
+                }
+            }
+        }
+    }
+
+    /// Whether a path uses the representation chosen for each of its
+    /// arrays, since auto array mode collects values using both
+    pub fn keeps_path(&self, path: &Path) -> bool {
+        if self.options.arrays != ArrayMode::Auto {
+            return true;
+        }
+
+        // Arrays along a scope are never recorded so they
+        // keep whichever representation the scope gave them
+        let mut prefix = Path::default();
+        for segment in path.segments() {
+            let is_tuple = self
+                .array_lengths
+                .get(&prefix)
+                .map(|len| len.is_some_and(|len| len <= path::MAX_TUPLE_LENGTH));
+            match (segment, is_tuple) {
+                (Segment::Index(_), Some(false)) | (Segment::Element, Some(true)) => return false,
+                _ => prefix = prefix.child(segment.clone()),
+            }
+        }
+
+        true
+    }
+
+    /// Whether a value contains other values which should be traversed
+    pub fn is_nested(&self, value: &JsonValue) -> bool {
+        (value.is_object() || value.is_array()) && !(self.options.keep_empty && value.is_empty())
+    }
+
+    /// Find the index of a value at a path, or `None` if the value is ignored
+    pub fn intern(&mut self, path: &Path, value: &JsonValue) -> Option<usize> {
+        if !self.allows(path) {
+            return None;
+        }
+
+        let normalization = self.options.normalization(path);
+        let normalized;
+        let value = if normalization.is_identity() {
+            value
+        } else {
+            normalized = normalization.apply(value);
+            &normalized
+        };
+
+        let index = self.index(value)?;
+        if self.options.profile.is_enabled() {
+            let profile = self.profiles.entry(path.clone()).or_default();
+            profile.count += 1;
+            if let Some(s) = value.as_str() {
+                profile.strings += 1;
+                profile.string_length += s.chars().count() as u64;
+            }
+        }
+
+        Some(index)
+    }
+
+    /// Find the index of a normalized value, or `None` if the value is ignored
+    fn index(&mut self, value: &JsonValue) -> Option<usize> {
+        if value.is_null() {
+            match self.options.nulls {
+                NullSemantics::Ignore => return None,
+                NullSemantics::NullEqualsNull => {}
+                NullSemantics::NullNotEqualsNull => {
+                    // Each null gets an index which is never reused
+                    self.by_index.push(Rc::from("null"));
+                    self.count += 1;
+                    return Some(self.count - 1);
+                }
+            }
+        } else if value.is_empty() && !self.options.keep_empty {
+            // Only strings and containers are empty, but not 0 or false
+            if value.is_string() || value.is_object() || value.is_array() {
+                return None;
+            }
+        }
+
+        let str_value = value.dump();
+        Some(match self.indices.get(str_value.as_str()) {
+            Some(index) => *index,
+            None => {
+                let str_value: Rc<str> = Rc::from(str_value);
+                self.indices.insert(str_value.clone(), self.count);
+                self.by_index.push(str_value);
+                self.count += 1;
+
+                self.count - 1
+            }
+        })
+    }
+
+    /// Look up the value with a given index
+    pub fn value(&self, index: usize) -> JsonValue {
+        self.by_index
+            .get(index)
+            .map_or(JsonValue::Null, |value| json::parse(value).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(nulls: NullSemantics, keep_empty: bool) -> Values {
+        Values::new(ValueOptions {
+            nulls,
+            keep_empty,
+            ..ValueOptions::default()
+        })
+    }
+
+    #[test]
+    fn it_ignores_nulls_and_empty_values_by_default() {
+        let mut values = Values::default();
+
+        assert_eq!(values.intern(&Path::default(), &JsonValue::Null), None);
+        assert_eq!(values.intern(&Path::default(), &"".into()), None);
+        assert_eq!(values.intern(&Path::default(), &0.into()), Some(0));
+        assert!(values.is_nested(&json::array![]));
+    }
+
+    #[test]
+    fn it_compares_nulls_as_equal() {
+        let mut values = values(NullSemantics::NullEqualsNull, false);
+
+        assert_eq!(values.intern(&Path::default(), &JsonValue::Null), Some(0));
+        assert_eq!(values.intern(&Path::default(), &JsonValue::Null), Some(0));
+        assert_eq!(values.value(0), JsonValue::Null);
+    }
+
+    #[test]
+    fn it_compares_nulls_as_distinct() {
+        let mut values = values(NullSemantics::NullNotEqualsNull, false);
+
+        assert_eq!(values.intern(&Path::default(), &JsonValue::Null), Some(0));
+        assert_eq!(values.intern(&Path::default(), &"null".into()), Some(1));
+        assert_eq!(values.intern(&Path::default(), &JsonValue::Null), Some(2));
+        assert_eq!(values.len(), 3);
+        assert_eq!(values.value(1), "null");
+        assert_eq!(values.value(2), JsonValue::Null);
+    }
+
+    #[test]
+    fn it_normalizes_numbers() {
+        let normalization = Normalization::from_steps(&[Normalize::Numbers]);
+
+        assert_eq!(
+            normalization.apply(&json::parse("1.0").unwrap()).dump(),
+            "1"
+        );
+        assert_eq!(
+            normalization.apply(&json::parse("1e2").unwrap()).dump(),
+            "100"
+        );
+        assert_eq!(normalization.apply(&1.5.into()).dump(), "1.5");
+        assert_eq!(
+            normalization.apply(&json::parse("-150e-2").unwrap()).dump(),
+            "-1.5"
+        );
+        assert_eq!(normalization.apply(&"1.0".into()).dump(), "\"1.0\"");
+    }
+
+    #[test]
+    fn it_normalizes_large_integers_exactly() {
+        let normalization = Normalization::from_steps(&[Normalize::Numbers]);
+        let a = normalization.apply(&json::parse("9007199254740993").unwrap());
+        let b = normalization.apply(&json::parse("9007199254740992.0").unwrap());
+
+        assert_eq!(a.dump(), "9007199254740993");
+        assert_eq!(b.dump(), "9007199254740992");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn it_normalizes_strings() {
+        let normalization = Normalization::from_steps(&[
+            Normalize::CaseFold,
+            Normalize::Coerce,
+            Normalize::Numbers,
+            Normalize::Trim,
+        ]);
+
+        assert_eq!(normalization.apply(&" ABC ".into()), "abc");
+        assert_eq!(normalization.apply(&" 1.0".into()), 1);
+        assert_eq!(normalization.apply(&"NaN".into()), "nan");
+
+        let case_fold = Normalization::from_steps(&[Normalize::CaseFold]);
+        assert_eq!(case_fold.apply(&"Straße".into()), "strasse");
+        assert_eq!(case_fold.apply(&"STRASSE".into()), "strasse");
+        assert_eq!(
+            case_fold.apply(&"ΣΊΣΥΦΟΣ".into()),
+            case_fold.apply(&"σίσυφος".into())
+        );
+
+        let nfc = Normalization::from_steps(&[Normalize::Nfc]);
+        assert_eq!(nfc.apply(&"e\u{301}".into()), "\u{e9}");
+    }
+
+    #[test]
+    fn it_normalizes_values_at_specific_paths() {
+        let mut options = ValueOptions::default();
+        options.path_normalize.insert(
+            Path::parse("id"),
+            Normalization::from_steps(&[Normalize::Coerce]),
+        );
+        let mut values = Values::new(options);
+
+        assert_eq!(values.intern(&Path::parse("id"), &1.into()), Some(0));
+        assert_eq!(values.intern(&Path::parse("id"), &"1".into()), Some(0));
+        assert_eq!(values.intern(&Path::parse("name"), &"1".into()), Some(1));
+    }
+
+    #[test]
+    fn it_identifies_tuples_in_auto_array_mode() {
+        let mut values = Values::new(ValueOptions {
+            arrays: ArrayMode::Auto,
+            ..ValueOptions::default()
+        });
+        values.record_arrays(
+            &Path::default(),
+            &json::object! {point: [1, 2], tags: ["a"]},
+        );
+        values.record_arrays(
+            &Path::default(),
+            &json::object! {point: [3, 4], tags: ["b", "c"]},
+        );
+
+        assert!(values.keeps_path(&Path::parse("point[0]")));
+        assert!(!values.keeps_path(&Path::parse("point[*]")));
+        assert!(values.keeps_path(&Path::parse("tags[*]")));
+        assert!(!values.keeps_path(&Path::parse("tags[0]")));
+    }
+
+    #[test]
+    fn it_keeps_empty_values() {
+        let mut values = values(NullSemantics::Ignore, true);
+
+        assert_eq!(values.intern(&Path::default(), &"".into()), Some(0));
+        assert!(!values.is_nested(&json::array![]));
+        assert_eq!(values.intern(&Path::default(), &json::array![]), Some(1));
+        assert_eq!(values.intern(&Path::default(), &json::object! {}), Some(2));
+    }
+}